@@ -0,0 +1,136 @@
+//! Indexing and query throughput on synthetic Zipfian corpora. Runs offline and reproducibly:
+//! corpora and queries come from fixed seeds.
+//!
+//!     cargo bench
+//!
+//! SIRKA_BENCH_DOCS sets the number of indexed documents, 100000 by default.
+
+extern crate sirka;
+
+use std::{env,fs,process};
+use std::time::{Duration,Instant};
+use sirka::*;
+use sirka::random::XorShift;
+
+const SEED: u64 = 1;
+
+/// Run `f` repeatedly for at least a fraction of a second and report the time per run
+fn measure<T, F: FnMut() -> T>(name: &str, items: usize, mut f: F) {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs == 0 || start.elapsed() < Duration::from_millis(300) {
+        let _ = f();
+        runs += 1;
+    }
+    let per_run = start.elapsed() / runs;
+    let throughput = items as f64 / per_run.as_secs_f64();
+    println!("{:<40} {:>12.3?}/run {:>14.0} items/s", name, per_run, throughput);
+}
+
+fn postings(docs: Vec<DocId>) -> VecPostings {
+    Postings {
+        tfs: (0..docs.len() as DocId + 1).collect(),
+        positions: vec![0; docs.len()],
+        docs,
+    }
+}
+
+fn cursor(p: &VecPostings) -> RawCursor<SliceSequence<'_>, SliceSequence<'_>, SliceSequence<'_>> {
+    RawCursor::new(Postings {
+        docs: (&p.docs).to_sequence(),
+        tfs: (&p.tfs).to_sequence(),
+        positions: (&p.positions).to_sequence(),
+    })
+}
+
+/// Random ascending DocIds below `max_doc`
+fn random_docs(rng: &mut XorShift, len: usize, max_doc: u64) -> Vec<DocId> {
+    let mut docs = (0..len).map(|_| rng.below(max_doc)).collect::<Vec<_>>();
+    docs.sort();
+    docs.dedup();
+    docs
+}
+
+fn bench_index(num_docs: usize, compact: bool) {
+    let dir = env::temp_dir().join(format!("sirka-bench-{}", process::id()));
+    let trie = if compact { "compact trie" } else { "trie" };
+    let _ = fs::remove_dir_all(&dir);
+    let corpus = Corpus::new(CorpusConfig { seed: SEED, ..Default::default() });
+    let vocabulary = corpus.vocabulary().to_vec();
+    let docs = corpus.take(num_docs).collect::<Vec<_>>();
+    let num_terms = docs.iter().map(|d| d.len()).sum::<usize>();
+
+    let start = Instant::now();
+    let mut writer = IndexWriter::open(&dir).unwrap();
+    writer.set_compact_trie(compact);
+    writer.add_documents(&docs).unwrap();
+    writer.commit().unwrap();
+    let elapsed = start.elapsed();
+    println!("{:<40} {:>12.3?}     {:>14.0} terms/s", format!("index {} docs, {}", num_docs, trie), elapsed,
+             num_terms as f64 / elapsed.as_secs_f64());
+
+    let searcher = Searcher::open(&dir).unwrap();
+    for segment in searcher.segments() {
+        for (stream, size) in segment.stream_sizes() {
+            println!("{:<40} {:>12} bytes {:>11.2} bytes/term", format!("size of {}", stream), size,
+                     size as f64 / num_terms as f64);
+        }
+    }
+
+    let segment = &searcher.segments()[0];
+    let dict = segment.dict();
+    let mut rng = XorShift::new(SEED);
+    let lookups = (0..1000).map(|_| vocabulary[rng.below(vocabulary.len() as u64) as usize].clone()).collect::<Vec<_>>();
+    measure(&format!("find_term, 1000 terms, {}", trie), lookups.len(), || {
+        lookups.iter().filter(|term| dict.find_term(term, false).is_some()).count()
+    });
+    let prefixes = lookups.iter().map(|term| term[..2].to_string()).collect::<Vec<_>>();
+    measure(&format!("find_term, 1000 prefixes, {}", trie), prefixes.len(), || {
+        prefixes.iter().filter(|prefix| dict.find_term(prefix, true).is_some()).count()
+    });
+
+    // Frequent and rare terms together
+    let queries = (0..100).map(|i| vec![vocabulary[i % 10].clone(), vocabulary[100 + i].clone()]).collect::<Vec<_>>();
+    measure(&format!("search, 100 queries, {}", trie), queries.len(), || {
+        queries.iter().map(|q| searcher.count(q, true)).sum::<usize>()
+    });
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn bench_cursors() {
+    let mut rng = XorShift::new(SEED);
+    let max_doc = 10_000_000;
+    let long = postings(random_docs(&mut rng, 1_000_000, max_doc));
+    let long2 = postings(random_docs(&mut rng, 1_000_000, max_doc));
+    let short = postings(random_docs(&mut rng, 1_000, max_doc));
+
+    measure("intersect long x long", long.docs.len() * 2, || {
+        Intersect::new(vec![cursor(&long), cursor(&long2)]).doc_ids().count()
+    });
+    measure("intersect short x long", short.docs.len() + long.docs.len(), || {
+        Intersect::new(vec![cursor(&short), cursor(&long)]).doc_ids().count()
+    });
+    measure("merge long + long", long.docs.len() * 2, || {
+        Merge::new(vec![cursor(&long), cursor(&long2)]).doc_ids().count()
+    });
+    measure("merge short + long", short.docs.len() + long.docs.len(), || {
+        Merge::new(vec![cursor(&short), cursor(&long)]).doc_ids().count()
+    });
+    measure("union long + long, with positions", long.docs.len() * 2, || {
+        MergerWithoutDuplicates::new(vec![cursor(&long), cursor(&long2)]).collect().docs.len()
+    });
+
+    // Many short postings, like the terms under a frequent prefix
+    let many = (0..1000).map(|_| postings(random_docs(&mut rng, 100, max_doc))).collect::<Vec<_>>();
+    measure("union 1000 x short", many.len() * 100, || {
+        MergerWithoutDuplicates::new(many.iter().map(cursor).collect()).doc_ids().count()
+    });
+}
+
+fn main() {
+    let num_docs = env::var("SIRKA_BENCH_DOCS").ok().and_then(|n| n.parse().ok()).unwrap_or(100_000);
+    bench_index(num_docs, false);
+    bench_index(num_docs, true);
+    bench_cursors();
+}
@@ -1,146 +1,92 @@
-#[macro_use]
 extern crate sirka;
 
-use std::path::Path;
-use std::io::{BufReader,Read};
-use std::fs::File;
 use sirka::*;
 
-static USAGE: &'static str = "usage: search <indexdir> <term>";
-
-fn create_reader(dirname: &Path, filename: &str) -> BufReader<File> {
-    let path = dirname.join(Path::new(filename));
-    let reader = BufReader::new(File::open(&path).unwrap());
-    reader
-}
+static USAGE: &str = "usage: search [--explain | [--offset <n>] [--limit <n>] [--order docid|score]] [--exact] [--fst] [--threads <n>] <indexdir> <term>...";
 
 fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() < 3 {
+    let mut args: Vec<_> = std::env::args().collect();
+    let mut explain = false;
+    let mut exact = false;
+    let mut fst = false;
+    let mut threads = 1;
+    let mut offset = 0;
+    let mut limit = None;
+    let mut order = Order::DocId;
+    while args.len() > 1 && args[1].starts_with("--") {
+        match args.remove(1).as_str() {
+            "--explain" => explain = true,
+            "--exact" => exact = true,
+            "--fst" => fst = true,
+            "--threads" if args.len() > 1 => match args.remove(1).parse() {
+                Ok(n) if n > 0 => threads = n,
+                _ => {
+                    println!("{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            "--offset" if args.len() > 1 => match args.remove(1).parse() {
+                Ok(n) => offset = n,
+                _ => {
+                    println!("{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            "--limit" if args.len() > 1 => match args.remove(1).parse() {
+                Ok(n) => limit = Some(n),
+                _ => {
+                    println!("{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            "--order" if args.len() > 1 => match args.remove(1).as_str() {
+                "docid" => order = Order::DocId,
+                "score" => order = Order::Score,
+                _ => {
+                    println!("{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            _ => {
+                println!("{}", USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+    // Pages are not explained
+    let paged = limit.is_some() || offset > 0 || order != Order::DocId;
+    if args.len() < 3 || (explain && paged) {
         println!("{}", USAGE);
         std::process::exit(1);
     }
 
-    let indexdir = Path::new(&args[1]);
+    let indexdir = &args[1];
     let query_to_seach = &args[2..];
 
+    let mut searcher = Searcher::open(indexdir).unwrap();
+    searcher.set_threads(threads);
+    searcher.set_fst_lookups(fst);
+    if paged {
+        let page = searcher.search_page(query_to_seach, exact, order, offset, limit.unwrap_or(10));
+        println!("Found in {} docs!", page.total);
+        for hit in &page.hits {
+            match order {
+                Order::DocId => println!("{}", hit.doc_id),
+                Order::Score => println!("{} {:.4}", hit.doc_id, hit.score),
+            }
+        }
+        return;
+    }
 
-    let mut meta_reader = create_reader(indexdir, "meta");
-    let mut buf = Vec::new();
-    meta_reader.read_to_end(&mut buf).unwrap();
-
-    let meta = IndexMeta::from_bytes(&buf[..]);
-
-    let mut trie_reader = create_reader(indexdir, "dict");
-    let dictbuf = StaticTrie::read(&mut trie_reader);
-    let dict = StaticTrie::new(&dictbuf[..], meta.dict_size as usize, meta.root_ptr as usize, meta.term_buffer_size as usize);
-
-    let mut docs_reader = create_reader(indexdir, "docs");
-    let mut docsbuf = Vec::new();
-    docs_reader.read_to_end(&mut docsbuf).unwrap();
-
-    let mut tfs_reader = create_reader(indexdir, "tfs");
-    let mut tfsbuf = Vec::new();
-    tfs_reader.read_to_end(&mut tfsbuf).unwrap();
-
-    let mut pos_reader = create_reader(indexdir, "positions");
-    let mut posbuf = Vec::new();
-    pos_reader.read_to_end(&mut posbuf).unwrap();
-
-    let input_postings = Postings {
-        docs: bytes_to_typed(&docsbuf).to_sequence(),
-        tfs: bytes_to_typed(&tfsbuf).to_sequence(),
-        positions: bytes_to_typed(&posbuf).to_sequence(),
-    };
-
-    let exact = false;
-    if let Some(result) = query(&dict, &input_postings, exact, query_to_seach) {
-        println!("Found in {} docs!", result.docs.len());
-        // println!("docs: {:?}", result.docs);
-        // println!("tfs: {:?}", result.tfs);
-        // println!("positions: {:?}", result.positions);
+    let mut explanation = if explain { Some(Explanation::new()) } else { None };
+    let result = searcher.search(query_to_seach, exact, explanation.as_mut());
+    if !result.is_empty() {
+        println!("Found in {} docs!", result.len());
+        // println!("docs: {:?}", result);
     } else {
         println!("Not found!");
     }
-}
-
-fn get_postings<DS, TS, PS>(ptr: usize, len: usize, p: &Postings<DS, TS, PS>) -> Postings<DS, TS, PS>
-    where DS: Sequence,
-          TS: Sequence,
-          PS: Sequence
-{
-    Postings {
-        docs: p.docs.subsequence(ptr, len),
-        tfs: p.tfs.subsequence(ptr, len + 1),
-        positions: p.positions.clone(),
-    }
-}
-
-/*
-fn prefix_cursor<DS, TS, PS>(header: &TrieNodeHeader, p: &Postings<DS, TS, PS>) -> Merge<RawCursor<DS, TS, PS>>
-    where DS: Sequence,
-          TS: Sequence,
-          PS: Sequence,
-{
-    let mut cursors_to_merge = Vec::with_capacity(2);
-    println!("header {:?}", header);
-    if header.num_postings > 0 {
-        println!("Choosing prefix postings: {}", header.num_postings);
-        let postings = get_postings(header.postings_ptr as usize, header.num_postings as usize, p);
-        cursors_to_merge.push(RawCursor::new(postings));
-    }
-    if header.num_prefix_postings > 0 {
-        println!("Choosing postings: {}", header.num_postings);
-        let prefix_postings_ptr = (header.postings_ptr + header.num_postings) as usize;
-        let prefix_postings_len = header.num_prefix_postings as usize;
-        let postings = get_postings(prefix_postings_ptr, prefix_postings_len, p);
-        cursors_to_merge.push(RawCursor::new(postings));
-    }
-    println!("");
-    Merge::new(cursors_to_merge)
-}
-*/
-
-fn find_terms<'a>(dict: &'a StaticTrie, exact: bool, query: &[&str]) -> Option<Vec<&'a TrieNodeHeader>> {
-    let mut headers = Vec::new();
-    for term in query.iter() {
-        match dict.find_term(term, !exact) {
-            Some(header) => headers.push(header),
-            None => return None,
-        }
+    if let Some(explanation) = explanation {
+        print!("{}", explanation);
     }
-    Some(headers)
-}
-
-fn search_daat<C: PostingsCursor>(mut term_cursors: Vec<C>) -> VecPostings {
-    // sort sequences ascending by their size to make daat skipping much faster
-    term_cursors.sort_by(|a, b| {
-        a.remains().cmp(&b.remains())
-    });
-    Intersect::new(term_cursors).collect()
-}
-
-fn query<STRING, DS, TS, PS>(dict: &StaticTrie, postings: &Postings<DS, TS, PS>, exact: bool, q: &[STRING]) -> Option<VecPostings>
-    where STRING: AsRef<str>,
-          DS: Sequence,
-          TS: Sequence,
-          PS: Sequence,
-{
-    let q = q.iter().map(|s| s.as_ref()).collect::<Vec<&str>>();
-    println!("Searching query: {:?}", &q);
-    let term_headers = tryopt!(find_terms(&dict, exact, &q));
-
-    //if exact {
-        let term_cursors = term_headers.iter().map(|th| {
-            println!("Term found. term='{}', numdocs={}", th.term_id, th.num_postings);
-            RawCursor::new(get_postings(th.postings_ptr as usize, th.num_postings as usize, postings))
-        }).collect();
-        Some(search_daat(term_cursors))
-    //} else {
-    //    let term_cursors = term_headers.iter().map(|th| {
-    //        println!("Term found. term='{}', numdocs={}", th.term_id, th.num_postings);
-    //        prefix_cursor(th, postings)
-    //    }).collect();
-    //    Some(search_daat(term_cursors))
-    //}
 }
@@ -0,0 +1,214 @@
+extern crate sirka;
+
+use std::collections::BTreeMap;
+use sirka::*;
+
+static USAGE: &str = "usage: sirka-dump <indexdir> [--json] [--term <term>]...";
+
+struct DictStats {
+    terms: usize,
+    nodes: usize,
+    // depth of term leaves -> count
+    depths: BTreeMap<usize, usize>,
+    // num_children of inner nodes -> count
+    fanout: BTreeMap<u32, usize>,
+}
+
+fn dict_stats(dict: &StaticTrie) -> DictStats {
+    let mut stats = DictStats {
+        terms: 0,
+        nodes: 0,
+        depths: BTreeMap::new(),
+        fanout: BTreeMap::new(),
+    };
+    for entry in dict.iter() {
+        stats.nodes += 1;
+        if entry.node.is_leaf() {
+            stats.terms += 1;
+            *stats.depths.entry(entry.depth).or_insert(0) += 1;
+        } else {
+            *stats.fanout.entry(entry.node.num_children).or_insert(0) += 1;
+        }
+    }
+    stats
+}
+
+struct DocPostings {
+    doc: DocId,
+    tf: DocId,
+    positions: Vec<DocId>,
+}
+
+fn decode_postings(header: &StaticNode, docs: &[DocId], tfs: &[DocId], positions: &[DocId]) -> Vec<DocPostings> {
+    let ptr = header.postings_ptr as usize;
+    let len = header.num_postings as usize;
+    (ptr..ptr + len).map(|i| {
+        // Positions are delta encoded within each document
+        let mut last = 0;
+        let positions = positions[tfs[i] as usize .. tfs[i + 1] as usize].iter().enumerate().map(|(j, &delta)| {
+            last = if j == 0 { delta } else { last + delta };
+            last
+        }).collect();
+        DocPostings {
+            doc: docs[i],
+            tf: tfs[i + 1] - tfs[i],
+            positions,
+        }
+    }).collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_histogram<K: std::fmt::Display>(h: &BTreeMap<K, usize>) -> String {
+    let items = h.iter().map(|(k, v)| format!("\"{}\": {}", k, v)).collect::<Vec<_>>();
+    format!("{{{}}}", items.join(", "))
+}
+
+fn json_list(xs: &[DocId]) -> String {
+    let items = xs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+fn dump_segment_json(segment: &Segment, terms: &[String]) -> String {
+    let meta = segment.meta();
+    let dict = segment.dict();
+    let stats = dict_stats(&dict);
+    let postings = bytes_postings(segment);
+
+    let mut out = Vec::new();
+    out.push(format!("\"name\": {}, \"num_docs\": {}, \"num_deleted\": {}, \"base\": {}",
+                     json_string(&segment.info.name), segment.info.num_docs, segment.info.num_deleted, segment.base));
+    out.push(format!("\"meta\": {{\"dict_size\": {}, \"root_ptr\": {}, \"term_buffer_size\": {}, \"docs_size\": {}, \"tfs_size\": {}, \"positions_size\": {}, \"flags\": {}}}",
+                     meta.dict_size, meta.root_ptr, meta.term_buffer_size, meta.docs_size, meta.tfs_size, meta.positions_size, meta.flags));
+    out.push(format!("\"dictionary\": {{\"terms\": {}, \"nodes\": {}, \"depths\": {}, \"fanout\": {}}}",
+                     stats.terms, stats.nodes, json_histogram(&stats.depths), json_histogram(&stats.fanout)));
+    let sizes = segment.stream_sizes().iter().map(|&(name, size)| format!("\"{}\": {}", name, size)).collect::<Vec<_>>();
+    out.push(format!("\"streams\": {{{}}}", sizes.join(", ")));
+    if !terms.is_empty() {
+        let found = terms.iter().map(|term| {
+            match dict.find_exact(term) {
+                Some(header) => {
+                    let docs = decode_postings(&header, postings.0, postings.1, postings.2).iter().map(|d| {
+                        format!("{{\"doc\": {}, \"tf\": {}, \"positions\": {}}}", d.doc, d.tf, json_list(&d.positions))
+                    }).collect::<Vec<_>>();
+                    format!("{}: {{\"term_id\": {}, \"num_postings\": {}, \"postings\": [{}]}}",
+                            json_string(term), header.term_id, header.num_postings, docs.join(", "))
+                }
+                None => format!("{}: null", json_string(term)),
+            }
+        }).collect::<Vec<_>>();
+        out.push(format!("\"terms\": {{{}}}", found.join(", ")));
+    }
+    format!("{{{}}}", out.join(", "))
+}
+
+fn dump_segment(segment: &Segment, terms: &[String]) {
+    let meta = segment.meta();
+    let dict = segment.dict();
+    let stats = dict_stats(&dict);
+    let postings = bytes_postings(segment);
+
+    println!("segment {}: num_docs={}, num_deleted={}, base={}",
+             segment.info.name, segment.info.num_docs, segment.info.num_deleted, segment.base);
+    println!("  meta:");
+    println!("    dict_size: {}", meta.dict_size);
+    println!("    root_ptr: {}", meta.root_ptr);
+    println!("    term_buffer_size: {}", meta.term_buffer_size);
+    println!("    docs_size: {}", meta.docs_size);
+    println!("    tfs_size: {}", meta.tfs_size);
+    println!("    positions_size: {}", meta.positions_size);
+    println!("    trie mode: {:?}", segment.trie_mode());
+    println!("    trie format: {:?}", segment.trie_format());
+    if let Some(fst) = segment.fst_dict() {
+        println!("    fst dictionary: {} terms", fst.len());
+    }
+    println!("  dictionary:");
+    println!("    terms: {}", stats.terms);
+    println!("    nodes: {}", stats.nodes);
+    println!("    term depths:");
+    for (depth, count) in &stats.depths {
+        println!("      {}: {}", depth, count);
+    }
+    println!("    fan-out (num_children of inner nodes):");
+    for (fanout, count) in &stats.fanout {
+        println!("      {}: {}", fanout, count);
+    }
+    println!("  streams:");
+    for (name, size) in segment.stream_sizes() {
+        println!("    {}: {} bytes", name, size);
+    }
+    for term in terms {
+        match dict.find_exact(term) {
+            Some(header) => {
+                println!("  term '{}': term_id={}, num_postings={}", term, header.term_id, header.num_postings);
+                for d in decode_postings(&header, postings.0, postings.1, postings.2) {
+                    println!("    doc={} tf={} positions={:?}", d.doc, d.tf, d.positions);
+                }
+            }
+            None => println!("  term '{}': not found", term),
+        }
+    }
+}
+
+fn bytes_postings(segment: &Segment) -> (&[DocId], &[DocId], &[DocId]) {
+    let p = segment.postings();
+    (p.docs.as_slice(), p.tfs.as_slice(), p.positions.as_slice())
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args().collect();
+    if args.len() < 2 {
+        println!("{}", USAGE);
+        std::process::exit(1);
+    }
+
+    let indexdir = &args[1];
+    let mut json = false;
+    let mut terms = Vec::new();
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--term" => match rest.next() {
+                Some(term) => terms.push(term.clone()),
+                None => {
+                    println!("{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            _ => {
+                println!("{}", USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let searcher = Searcher::open(indexdir).unwrap();
+    if json {
+        let segments = searcher.segments().iter().map(|s| dump_segment_json(s, &terms)).collect::<Vec<_>>();
+        println!("{{\"generation\": {}, \"num_docs\": {}, \"num_deleted\": {}, \"segments\": [{}]}}",
+                 searcher.generation(), searcher.num_docs(), searcher.num_deleted(), segments.join(", "));
+    } else {
+        println!("generation: {}", searcher.generation());
+        println!("num_docs: {}", searcher.num_docs());
+        println!("num_deleted: {}", searcher.num_deleted());
+        for segment in searcher.segments() {
+            dump_segment(segment, &terms);
+        }
+    }
+}
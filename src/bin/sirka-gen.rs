@@ -0,0 +1,55 @@
+extern crate sirka;
+
+use std::io::{self,Write,BufWriter};
+use sirka::*;
+
+static USAGE: &str = "usage: sirka-gen [--docs <n>] [--vocabulary <n>] [--zipf <exponent>] [--doc-len <min>-<max> | --mean-doc-len <n>] [--shared-prefixes <ratio>] [--unicode] [--seed <n>]";
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    std::process::exit(1);
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<String>) -> T {
+    arg.and_then(|a| a.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn main() {
+    let mut config = CorpusConfig::default();
+    let mut num_docs = 10_000;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--docs" => num_docs = parse(args.next()),
+            "--vocabulary" => match parse(args.next()) {
+                0 => usage(),
+                n => config.vocabulary_size = n,
+            },
+            "--zipf" => config.zipf_exponent = parse(args.next()),
+            "--doc-len" => {
+                let range = args.next().unwrap_or_else(|| usage());
+                let mut bounds = range.splitn(2, '-').map(|n| n.parse::<usize>().ok());
+                match (bounds.next().and_then(|n| n), bounds.next().and_then(|n| n)) {
+                    (Some(min), Some(max)) if 0 < min && min <= max => config.doc_len = DocLength::Uniform(min, max),
+                    _ => usage(),
+                }
+            }
+            "--mean-doc-len" => config.doc_len = DocLength::Geometric(parse(args.next())),
+            "--shared-prefixes" => config.shared_prefixes = parse(args.next()),
+            "--unicode" => config.unicode = true,
+            "--seed" => config.seed = parse(args.next()),
+            _ => usage(),
+        }
+    }
+
+    // Documents in the format `index` reads: one per line, terms separated by '|'
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for doc in Corpus::new(config).take(num_docs) {
+        if writeln!(out, "{}", doc.join("|")).is_err() {
+            // Closed pipe, eg. piped to `head`
+            return;
+        }
+    }
+    let _ = out.flush();
+}
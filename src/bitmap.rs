@@ -0,0 +1,76 @@
+use types::*;
+
+/// Growable set of DocIds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, doc_id: DocId) -> bool {
+        let (word, bit) = ((doc_id / 64) as usize, doc_id % 64);
+        word < self.words.len() && self.words[word] & (1 << bit) != 0
+    }
+
+    /// Returns false if the DocId was already present
+    pub fn insert(&mut self, doc_id: DocId) -> bool {
+        let (word, bit) = ((doc_id / 64) as usize, doc_id % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let present = self.words[word] & (1 << bit) != 0;
+        self.words[word] |= 1 << bit;
+        !present
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = DocId> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| i as DocId * 64 + bit)
+        })
+    }
+
+    pub fn from_bytes(bs: &[u8]) -> Self {
+        let words = bs.chunks(8).map(|chunk| {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        }).collect();
+        Bitmap { words }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap() {
+        let mut bitmap = Bitmap::new();
+        assert!(bitmap.is_empty());
+        assert!(bitmap.insert(3));
+        assert!(bitmap.insert(64));
+        assert!(bitmap.insert(200));
+        assert!(!bitmap.insert(64));
+        assert!(bitmap.contains(3) && bitmap.contains(64) && bitmap.contains(200));
+        assert!(!bitmap.contains(4) && !bitmap.contains(1000));
+        assert_eq!(bitmap.len(), 3);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![3, 64, 200]);
+        assert_eq!(Bitmap::from_bytes(&bitmap.to_bytes()), bitmap);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap,HashMap};
+use types::*;
+use util::{write_varint,read_varint};
+
+/// Query as a cache key. Query terms are intersected, so their order and repetitions do
+/// not matter. Results are valid only for the generation of the index they were found in
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QueryKey {
+    generation: u64,
+    exact: bool,
+    terms: Vec<String>,
+}
+
+impl QueryKey {
+    pub fn new<S: AsRef<str>>(generation: u64, query: &[S], exact: bool) -> Self {
+        let mut terms = query.iter().map(|s| s.as_ref().to_string()).collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+        QueryKey { generation, exact, terms }
+    }
+
+    fn size(&self) -> usize {
+        self.terms.iter().map(|t| t.len()).sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    /// Bytes taken by the cached doc lists and their keys
+    pub size: usize,
+}
+
+struct CacheEntry {
+    /// Ascending DocIds, delta and varint encoded
+    docs: Vec<u8>,
+    last_used: u64,
+}
+
+/// LRU cache of query results bounded by the size of the compressed doc lists
+pub struct QueryCache {
+    capacity: usize,
+    entries: HashMap<QueryKey, CacheEntry>,
+    /// last_used -> key, the least recently used first
+    lru: BTreeMap<u64, QueryKey>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize) -> Self {
+        QueryCache {
+            capacity,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &QueryKey) -> Option<Vec<DocId>> {
+        self.clock += 1;
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+        };
+        self.stats.hits += 1;
+        self.lru.remove(&entry.last_used);
+        entry.last_used = self.clock;
+        self.lru.insert(self.clock, key.clone());
+        Some(decode_docs(&entry.docs))
+    }
+
+    /// Cache the result of a query, evicting the least recently used ones as needed. Results
+    /// larger than the whole cache are not cached
+    pub fn insert(&mut self, key: QueryKey, docs: &[DocId]) {
+        let docs = encode_docs(docs);
+        let size = key.size() + docs.len();
+        if size > self.capacity {
+            return;
+        }
+        self.remove(&key);
+        while self.stats.size + size > self.capacity {
+            let oldest = self.lru.keys().next().cloned().unwrap();
+            let key = self.lru.remove(&oldest).unwrap();
+            self.remove(&key);
+        }
+        self.clock += 1;
+        self.lru.insert(self.clock, key.clone());
+        self.entries.insert(key, CacheEntry { docs, last_used: self.clock });
+        self.stats.size += size;
+        self.stats.entries += 1;
+    }
+
+    fn remove(&mut self, key: &QueryKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+            self.stats.size -= key.size() + entry.docs.len();
+            self.stats.entries -= 1;
+        }
+    }
+
+    /// Drop results of all other generations of the index
+    pub fn retain_generation(&mut self, generation: u64) {
+        let stale = self.entries.keys().filter(|k| k.generation != generation).cloned().collect::<Vec<_>>();
+        for key in &stale {
+            self.remove(key);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+fn encode_docs(docs: &[DocId]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut last = 0;
+    for &doc in docs {
+        write_varint(&mut out, doc - last);
+        last = doc;
+    }
+    out
+}
+
+fn decode_docs(bytes: &[u8]) -> Vec<DocId> {
+    let mut docs = Vec::new();
+    let (mut last, mut at) = (0, 0);
+    while at < bytes.len() {
+        last += read_varint(bytes, &mut at);
+        docs.push(last);
+    }
+    docs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru() {
+        let docs = vec![0, 1, 127, 128, 300, 1 << 40];
+        assert_eq!(decode_docs(&encode_docs(&docs)), docs);
+
+        let key = |term: &str| QueryKey::new(1, &[term], false);
+        let mut cache = QueryCache::new(12);
+        cache.insert(key("ab"), &[1, 2]);
+        cache.insert(key("cd"), &[3, 4]);
+        cache.insert(key("ef"), &[5, 6]);
+        assert_eq!(cache.get(&key("ab")), Some(vec![1, 2]));
+        // "cd" is the least recently used one
+        cache.insert(key("gh"), &[7]);
+        assert_eq!(cache.get(&key("cd")), None);
+        assert_eq!(cache.get(&key("ef")), Some(vec![5, 6]));
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 1, entries: 3, size: 11 });
+
+        assert_eq!(QueryKey::new(1, &["b", "a", "b"], true), QueryKey::new(1, &["a", "b"], true));
+        cache.retain_generation(2);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
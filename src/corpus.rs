@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use random::XorShift;
+
+/// Ranks `0..n` drawn with probability proportional to `1 / (rank + 1)^exponent`
+pub struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    pub fn new(n: usize, exponent: f64) -> Self {
+        assert!(n > 0);
+        let mut sum = 0.0;
+        let mut cdf = (0..n).map(|rank| {
+            sum += 1.0 / ((rank + 1) as f64).powf(exponent);
+            sum
+        }).collect::<Vec<_>>();
+        for p in &mut cdf {
+            *p /= sum;
+        }
+        Zipf { cdf }
+    }
+
+    pub fn sample(&self, rng: &mut XorShift) -> usize {
+        let x = rng.next_f64();
+        self.cdf.partition_point(|&p| p <= x).min(self.cdf.len() - 1)
+    }
+}
+
+/// Distribution of the number of terms of a document
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DocLength {
+    /// Uniform between the bounds, both included
+    Uniform(usize, usize),
+    /// Geometric with the given mean, at least one term. Mostly short documents with a long tail
+    Geometric(f64),
+}
+
+impl DocLength {
+    fn sample(&self, rng: &mut XorShift) -> usize {
+        match *self {
+            DocLength::Uniform(min, max) => rng.range(min as u64, max as u64 + 1) as usize,
+            DocLength::Geometric(mean) => {
+                let p = 1.0 / mean.max(1.0);
+                let mut len = 1;
+                while !rng.chance(p) {
+                    len += 1;
+                }
+                len
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CorpusConfig {
+    pub seed: u64,
+    pub vocabulary_size: usize,
+    pub zipf_exponent: f64,
+    pub doc_len: DocLength,
+    /// Fraction of terms made by extending a prefix of another term, eg. "band" to "bandana"
+    /// or "bar", so that terms share prefixes and the trie forks a lot
+    pub shared_prefixes: f64,
+    /// Draw letters of terms from several scripts, some of them outside ASCII
+    pub unicode: bool,
+}
+
+impl Default for CorpusConfig {
+    fn default() -> Self {
+        CorpusConfig {
+            seed: 0,
+            vocabulary_size: 10_000,
+            zipf_exponent: 1.0,
+            doc_len: DocLength::Uniform(1, 20),
+            shared_prefixes: 0.0,
+            unicode: false,
+        }
+    }
+}
+
+/// Endless stream of synthetic documents with Zipf distributed terms. The same config always
+/// generates the same documents
+pub struct Corpus {
+    config: CorpusConfig,
+    rng: XorShift,
+    vocabulary: Vec<String>,
+    zipf: Zipf,
+}
+
+impl Corpus {
+    pub fn new(config: CorpusConfig) -> Self {
+        let mut rng = XorShift::new(config.seed);
+        let vocabulary = random_vocabulary(&mut rng, &config);
+        let zipf = Zipf::new(vocabulary.len(), config.zipf_exponent);
+        Corpus {
+            config,
+            rng,
+            vocabulary,
+            zipf,
+        }
+    }
+
+    /// Terms from the most frequent one
+    pub fn vocabulary(&self) -> &[String] {
+        &self.vocabulary
+    }
+}
+
+impl Iterator for Corpus {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Vec<String>> {
+        let len = self.config.doc_len.sample(&mut self.rng);
+        Some((0..len).map(|_| self.vocabulary[self.zipf.sample(&mut self.rng)].clone()).collect())
+    }
+}
+
+/// Letters of Unicode terms: Latin with diacritics, Greek, Cyrillic, CJK and emoji take one
+/// to four bytes in UTF-8
+const UNICODE_LETTERS: &str = "abcdeéèüßøαβγδжзиы中文字日本😀🎉";
+
+fn random_letter(rng: &mut XorShift, unicode: bool) -> char {
+    if unicode && rng.chance(0.5) {
+        let n = UNICODE_LETTERS.chars().count() as u64;
+        UNICODE_LETTERS.chars().nth(rng.below(n) as usize).unwrap()
+    } else {
+        (b'a' + rng.below(26) as u8) as char
+    }
+}
+
+/// Distinct words of 2 to 10 letters, or longer when extending another word
+fn random_vocabulary(rng: &mut XorShift, config: &CorpusConfig) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut vocabulary = Vec::with_capacity(config.vocabulary_size);
+    while vocabulary.len() < config.vocabulary_size {
+        let mut word = String::new();
+        if !vocabulary.is_empty() && rng.chance(config.shared_prefixes) {
+            let base: &String = &vocabulary[rng.below(vocabulary.len() as u64) as usize];
+            // Prefixes of any length, a single letter one too
+            let cut = rng.range(1, base.chars().count() as u64 + 1) as usize;
+            word.extend(base.chars().take(cut));
+        }
+        let len = rng.range(if word.is_empty() { 2 } else { 1 }, 11);
+        word.extend((0..len).map(|_| random_letter(rng, config.unicode)));
+        if seen.insert(word.clone()) {
+            vocabulary.push(word);
+        }
+    }
+    vocabulary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corpus() {
+        let config = CorpusConfig { seed: 42, vocabulary_size: 100, ..Default::default() };
+        let docs = Corpus::new(config.clone()).take(1000).collect::<Vec<_>>();
+        assert_eq!(docs, Corpus::new(config).take(1000).collect::<Vec<_>>());
+        assert!(docs.iter().all(|doc| !doc.is_empty() && doc.len() <= 20));
+
+        let corpus = Corpus::new(CorpusConfig { seed: 42, vocabulary_size: 100, ..Default::default() });
+        let count = |term: &str| docs.iter().flatten().filter(|t| *t == term).count();
+        assert!(count(&corpus.vocabulary()[0]) > 5 * count(&corpus.vocabulary()[50]));
+    }
+
+    #[test]
+    fn test_shared_prefixes_and_unicode() {
+        let config = CorpusConfig {
+            vocabulary_size: 1000,
+            doc_len: DocLength::Geometric(5.0),
+            shared_prefixes: 0.5,
+            unicode: true,
+            ..Default::default()
+        };
+        let corpus = Corpus::new(config);
+        let vocabulary = corpus.vocabulary().to_vec();
+        let extended = vocabulary.iter().filter(|w| {
+            vocabulary.iter().any(|v| v != *w && w.starts_with(v.as_str()))
+        }).count();
+        assert!(extended > 100);
+        assert!(vocabulary.iter().any(|w| !w.is_ascii()));
+        assert!(vocabulary.iter().all(|w| !w.is_empty() && !w.contains('|') && !w.contains('\n')));
+        let docs = corpus.take(1000).collect::<Vec<_>>();
+        assert!(docs.iter().all(|doc| !doc.is_empty()));
+        assert!(docs.iter().any(|doc| doc.len() > 15));
+    }
+}
@@ -0,0 +1,119 @@
+use types::*;
+use nutrie::{StaticTrie,StaticNode};
+use fst::{FstDictionary,TermInfo};
+
+/// Postings of a term or of a stored union of terms in the postings streams of a segment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PostingsList {
+    pub postings_ptr: DocId,
+    pub num_postings: u64,
+}
+
+impl StaticNode {
+    pub fn postings_list(&self) -> PostingsList {
+        PostingsList { postings_ptr: self.postings_ptr, num_postings: self.num_postings }
+    }
+}
+
+impl TermInfo {
+    pub fn postings_list(&self) -> PostingsList {
+        PostingsList { postings_ptr: self.postings_ptr, num_postings: self.num_postings }
+    }
+}
+
+/// Postings of the terms found by a lookup. A single list is read as is, several lists are
+/// merged without duplicates when read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermPostings {
+    pub lists: Vec<PostingsList>,
+}
+
+impl TermPostings {
+    pub fn single(list: PostingsList) -> Self {
+        TermPostings { lists: vec![list] }
+    }
+
+    /// Number of postings of all lists. Documents present in several lists are counted once for
+    /// each of them
+    pub fn num_postings(&self) -> u64 {
+        self.lists.iter().map(|list| list.num_postings).sum()
+    }
+}
+
+impl<'a> StaticTrie<'a> {
+    /// Postings of all terms under the node: its merged postings if written, see
+    /// `PrefixPostings`, else those of its children, found the same way
+    ///
+    /// Deliberately, no cost is weighed at query time between written postings and merging
+    /// the children's, as first asked for: a union without duplicates never holds more
+    /// postings than the lists it replaces, so the merge is never the cheaper one
+    pub fn prefix_postings(&self, node: &StaticNode) -> TermPostings {
+        if node.is_leaf() || node.num_postings > 0 {
+            return TermPostings::single(node.postings_list());
+        }
+        TermPostings {
+            lists: self.children(node).iter().flat_map(|child| self.prefix_postings(child).lists).collect(),
+        }
+    }
+}
+
+/// Terms of a segment with their postings, whatever the dictionary layout
+pub trait TermDictionary {
+    /// Number of distinct terms
+    fn num_terms(&self) -> u64;
+
+    /// Postings of exactly this term
+    fn find_exact(&self, term: &str) -> Option<TermPostings>;
+
+    /// Postings of all terms starting with `prefix`, the prefix itself included
+    fn find_prefix(&self, prefix: &str) -> Option<TermPostings>;
+
+    /// All terms with their postings in ascending order
+    fn terms<'t>(&'t self) -> Box<dyn Iterator<Item = (String, PostingsList)> + 't>;
+
+    /// Number of documents containing the term
+    fn doc_freq(&self, term: &str) -> u64 {
+        self.find_exact(term).map_or(0, |postings| postings.num_postings())
+    }
+}
+
+/// Prefixes are answered by the merged postings of the node of all terms extending them
+impl<'a> TermDictionary for StaticTrie<'a> {
+    /// Leaves are counted, the trie does not store the number
+    fn num_terms(&self) -> u64 {
+        self.leaves().count() as u64
+    }
+
+    fn find_exact(&self, term: &str) -> Option<TermPostings> {
+        StaticTrie::find_exact(self, term).map(|node| TermPostings::single(node.postings_list()))
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Option<TermPostings> {
+        let postings = self.prefix_postings(&self.find_term(prefix, true)?);
+        if postings.lists.is_empty() { None } else { Some(postings) }
+    }
+
+    fn terms<'t>(&'t self) -> Box<dyn Iterator<Item = (String, PostingsList)> + 't> {
+        Box::new(self.leaves().map(|(term, node)| (term, node.postings_list())))
+    }
+}
+
+/// Prefixes are answered by the postings of every term starting with them
+impl<'a> TermDictionary for FstDictionary<'a> {
+    fn num_terms(&self) -> u64 {
+        self.len()
+    }
+
+    fn find_exact(&self, term: &str) -> Option<TermPostings> {
+        FstDictionary::find_exact(self, term).map(|info| TermPostings::single(info.postings_list()))
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Option<TermPostings> {
+        let lists = self.prefix(prefix).map(|(_, info)| info.postings_list()).collect::<Vec<_>>();
+        if lists.is_empty() { None } else { Some(TermPostings { lists }) }
+    }
+
+    fn terms<'t>(&'t self) -> Box<dyn Iterator<Item = (String, PostingsList)> + 't> {
+        Box::new(self.iter().map(|(term, info)| (term, info.postings_list())))
+    }
+}
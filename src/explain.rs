@@ -0,0 +1,130 @@
+use std::fmt;
+use types::*;
+use nutrie::{StaticTrie,StaticNode};
+use dictionary::TermPostings;
+
+pub struct TrieStep {
+    pub label: String,
+    pub term_id: TermId,
+    pub num_postings: u64,
+    pub num_children: u32,
+}
+
+pub struct TermLookup {
+    pub term: String,
+    pub path: Vec<TrieStep>,
+    pub found: bool,
+    /// Postings lists found in the `FstDictionary`, None when the trie was walked instead
+    pub fst_lists: Option<usize>,
+}
+
+pub struct CursorStats {
+    pub term: String,
+    pub postings: usize,
+    pub skipped: usize,
+}
+
+/// Record of how a query was evaluated. Filled only when explicitly asked for, eg. by
+/// `search --explain`
+#[derive(Default)]
+pub struct Explanation {
+    pub segments: Vec<SegmentExplanation>,
+}
+
+impl Explanation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// How the query was evaluated in a single segment
+pub struct SegmentExplanation {
+    pub segment: String,
+    pub lookups: Vec<TermLookup>,
+    /// Cursors in the order they were intersected
+    pub cursors: Vec<CursorStats>,
+    pub matches: usize,
+    /// Matches of deleted documents, filtered out of the results
+    pub deleted: usize,
+}
+
+impl SegmentExplanation {
+    pub fn new(segment: &str) -> Self {
+        SegmentExplanation {
+            segment: segment.into(),
+            lookups: Vec::new(),
+            cursors: Vec::new(),
+            matches: 0,
+            deleted: 0,
+        }
+    }
+
+    /// Look the term up in the dictionary and record the trie path taken
+    pub fn find_term(&mut self, dict: &StaticTrie, term: &str, exact: bool) -> Option<StaticNode> {
+        let mut path = Vec::new();
+        let on_step = |node: &StaticNode| {
+            path.push(TrieStep {
+                label: dict.label(node).into(),
+                term_id: node.term_id,
+                num_postings: node.num_postings,
+                num_children: node.num_children,
+            });
+        };
+        let found = if exact {
+            dict.find_exact_with(term, on_step)
+        } else {
+            dict.find_term_with(term, true, on_step)
+        };
+        self.lookups.push(TermLookup {
+            term: term.into(),
+            path,
+            found: found.is_some(),
+            fst_lists: None,
+        });
+        found
+    }
+
+    /// Record the postings found for the term in the `FstDictionary`, which has no path to
+    /// record
+    pub fn fst_lookup(&mut self, term: &str, postings: Option<&TermPostings>) {
+        self.lookups.push(TermLookup {
+            term: term.into(),
+            path: Vec::new(),
+            found: postings.is_some(),
+            fst_lists: Some(postings.map_or(0, |postings| postings.lists.len())),
+        });
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            write!(f, "{}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SegmentExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "segment {}:", self.segment)?;
+        for lookup in &self.lookups {
+            write!(f, "  term '{}': {}", lookup.term, if lookup.found { "found" } else { "not found" })?;
+            match lookup.fst_lists {
+                Some(lists) => writeln!(f, " in fst, lists={}", lists)?,
+                None => writeln!(f)?,
+            }
+            for step in &lookup.path {
+                writeln!(f, "    trie node '{}': term_id={}, postings={}, children={}",
+                         step.label, step.term_id, step.num_postings, step.num_children)?;
+            }
+        }
+        if !self.cursors.is_empty() {
+            writeln!(f, "  intersection: {} matches, {} deleted", self.matches, self.deleted)?;
+            for cursor in &self.cursors {
+                writeln!(f, "    cursor '{}': postings={}, skipped={}", cursor.term, cursor.postings, cursor.skipped)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+mod read;
+mod write;
+
+pub use self::write::*;
+pub use self::read::*;
+use std::convert::TryInto;
+use types::{DocId,TermId};
+
+/// Flag of `Fst` nodes at which keys end
+pub const FST_FINAL: u8 = 1;
+
+/// Postings of a term in a segment, stored in a table indexed by the outputs of an
+/// `FstDictionary`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TermInfo {
+    pub postings_ptr: DocId,
+    pub num_postings: u64,
+    pub term_id: TermId,
+}
+
+impl TermInfo {
+    /// Bytes of an entry in the table, all fields little endian
+    pub const SIZE: usize = 20;
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.postings_ptr.to_le_bytes());
+        out.extend_from_slice(&self.num_postings.to_le_bytes());
+        out.extend_from_slice(&self.term_id.to_le_bytes());
+    }
+
+    fn from_bytes(bs: &[u8]) -> Self {
+        TermInfo {
+            postings_ptr: u64::from_le_bytes(bs[0..8].try_into().unwrap()),
+            num_postings: u64::from_le_bytes(bs[8..16].try_into().unwrap()),
+            term_id: u32::from_le_bytes(bs[16..20].try_into().unwrap()),
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use std::convert::TryInto;
+use std::ops::Bound;
+use std::str;
+use util::read_varint;
+use fst::{TermInfo,FST_FINAL};
+
+/// Finite state transducer mapping byte strings to u64 outputs, written by `FstBuilder`.
+/// Unlike the trie it shares suffixes of keys as well as prefixes.
+///
+/// Nodes are written children first. A node is a flags byte (see `FST_FINAL`), the varint
+/// output of the key ending at it if final, the varint number of transitions and for every
+/// transition its label byte, varint output and the varint offset of its target back from
+/// the node. Outputs along the path of a key sum to its output. The root pointer and the
+/// number of keys follow the nodes as u64 little endian
+#[derive(Clone, Copy)]
+pub struct Fst<'a> {
+    bytes: &'a [u8],
+    root_ptr: usize,
+    len: u64,
+}
+
+/// Node decoded up to its transitions
+#[derive(Clone, Copy, Debug)]
+struct FstNode {
+    ptr: usize,
+    final_output: Option<u64>,
+    num_transitions: usize,
+    transitions_ptr: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Transition {
+    label: u8,
+    output: u64,
+    target: usize,
+}
+
+impl<'a> Fst<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        let footer = bytes.len() - 16;
+        Fst {
+            bytes: &bytes[..footer],
+            root_ptr: u64::from_le_bytes(bytes[footer..footer + 8].try_into().unwrap()) as usize,
+            len: u64::from_le_bytes(bytes[footer + 8..].try_into().unwrap()),
+        }
+    }
+
+    /// Number of keys
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn node_at(&self, ptr: usize) -> FstNode {
+        let bs = &self.bytes[ptr..];
+        let mut at = 1;
+        let final_output = if bs[0] & FST_FINAL != 0 { Some(read_varint(bs, &mut at)) } else { None };
+        let num_transitions = read_varint(bs, &mut at) as usize;
+        FstNode { ptr, final_output, num_transitions, transitions_ptr: ptr + at }
+    }
+
+    /// Transition of the node starting at `*at`, moves `*at` past it
+    fn transition(&self, node: &FstNode, at: &mut usize) -> Transition {
+        let label = self.bytes[*at];
+        *at += 1;
+        let output = read_varint(self.bytes, at);
+        let target = node.ptr - read_varint(self.bytes, at) as usize;
+        Transition { label, output, target }
+    }
+
+    fn find_transition(&self, node: &FstNode, label: u8) -> Option<Transition> {
+        let mut at = node.transitions_ptr;
+        for _ in 0..node.num_transitions {
+            let transition = self.transition(node, &mut at);
+            if transition.label >= label {
+                return if transition.label == label { Some(transition) } else { None };
+            }
+        }
+        None
+    }
+
+    /// Node reached by the key with the sum of the outputs on the way
+    fn walk(&self, key: &[u8]) -> Option<(FstNode, u64)> {
+        let mut node = self.node_at(self.root_ptr);
+        let mut output = 0;
+        for &label in key {
+            let transition = tryopt!(self.find_transition(&node, label));
+            output += transition.output;
+            node = self.node_at(transition.target);
+        }
+        Some((node, output))
+    }
+
+    /// Output of exactly this key
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        let (node, output) = tryopt!(self.walk(key));
+        node.final_output.map(|final_output| output + final_output)
+    }
+
+    /// All keys with their outputs in ascending order
+    pub fn iter(&self) -> FstStream<'a, AllKeys> {
+        self.search(AllKeys)
+    }
+
+    /// Keys starting with `prefix`
+    pub fn prefix(&self, prefix: &[u8]) -> FstStream<'a, AllKeys> {
+        let mut stream = FstStream { fst: *self, matcher: AllKeys, key: prefix.to_vec(), stack: Vec::new() };
+        if let Some((node, output)) = self.walk(prefix) {
+            stream.stack.push(Frame::new(node, prefix.len(), output, ()));
+        }
+        stream
+    }
+
+    /// Keys between the bounds
+    pub fn range(&self, from: Bound<&[u8]>, to: Bound<&[u8]>) -> FstStream<'a, KeyRange> {
+        self.search(KeyRange { from: from.map(|key| key.to_vec()), to: to.map(|key| key.to_vec()) })
+    }
+
+    /// Keys within `max_distance` edits of `key`, counting in chars. Keys need to be UTF-8
+    pub fn fuzzy(&self, key: &str, max_distance: u32) -> FstStream<'a, Levenshtein> {
+        self.search(Levenshtein { query: key.chars().collect(), max_distance })
+    }
+
+    /// Keys accepted by the matcher
+    pub fn search<M: Matcher>(&self, matcher: M) -> FstStream<'a, M> {
+        let start = Frame::new(self.node_at(self.root_ptr), 0, 0, matcher.start());
+        FstStream { fst: *self, matcher, key: Vec::new(), stack: vec![start] }
+    }
+}
+
+/// Automaton run along the keys of an `Fst` by `Fst::search`
+pub trait Matcher {
+    type State;
+
+    fn start(&self) -> Self::State;
+
+    /// State after the next byte of a key. None when no key continuing with it can match,
+    /// which prunes them all
+    fn accept(&self, state: &Self::State, byte: u8) -> Option<Self::State>;
+
+    fn is_match(&self, state: &Self::State) -> bool;
+}
+
+/// Matches every key
+pub struct AllKeys;
+
+impl Matcher for AllKeys {
+    type State = ();
+
+    fn start(&self) {}
+
+    fn accept(&self, _state: &(), _byte: u8) -> Option<()> {
+        Some(())
+    }
+
+    fn is_match(&self, _state: &()) -> bool {
+        true
+    }
+}
+
+/// Matches keys between two bounds
+pub struct KeyRange {
+    from: Bound<Vec<u8>>,
+    to: Bound<Vec<u8>>,
+}
+
+/// Key read so far is a prefix of the bounds, unless it already left them behind
+#[derive(Clone, Copy, Debug)]
+pub struct KeyRangeState {
+    depth: usize,
+    above_from: bool,
+    below_to: bool,
+}
+
+fn bound_key(bound: &Bound<Vec<u8>>) -> &[u8] {
+    match *bound {
+        Bound::Included(ref key) | Bound::Excluded(ref key) => key,
+        Bound::Unbounded => &[],
+    }
+}
+
+impl Matcher for KeyRange {
+    type State = KeyRangeState;
+
+    fn start(&self) -> KeyRangeState {
+        KeyRangeState {
+            depth: 0,
+            above_from: self.from == Bound::Unbounded,
+            below_to: self.to == Bound::Unbounded,
+        }
+    }
+
+    fn accept(&self, state: &KeyRangeState, byte: u8) -> Option<KeyRangeState> {
+        let mut next = KeyRangeState { depth: state.depth + 1, ..*state };
+        if !state.above_from {
+            match bound_key(&self.from).get(state.depth) {
+                Some(&b) if byte < b => return None,
+                Some(&b) if byte == b => {}
+                // Greater or longer than the lower bound
+                _ => next.above_from = true,
+            }
+        }
+        if !state.below_to {
+            match bound_key(&self.to).get(state.depth) {
+                Some(&b) if byte < b => next.below_to = true,
+                Some(&b) if byte == b => {}
+                _ => return None,
+            }
+        }
+        Some(next)
+    }
+
+    fn is_match(&self, state: &KeyRangeState) -> bool {
+        // Keys still on a bound are its prefixes
+        let above_from = state.above_from || match self.from {
+            Bound::Included(ref key) => state.depth == key.len(),
+            _ => false,
+        };
+        let below_to = state.below_to || match self.to {
+            Bound::Excluded(ref key) => state.depth < key.len(),
+            _ => true,
+        };
+        above_from && below_to
+    }
+}
+
+/// Matches keys within a Levenshtein distance of the query, counting in chars
+pub struct Levenshtein {
+    query: Vec<char>,
+    max_distance: u32,
+}
+
+/// Distances of the key read so far to every prefix of the query. Bytes of a char not
+/// complete yet are pending
+#[derive(Clone, Debug)]
+pub struct LevenshteinState {
+    distances: Vec<u32>,
+    pending: Vec<u8>,
+}
+
+impl Matcher for Levenshtein {
+    type State = LevenshteinState;
+
+    fn start(&self) -> LevenshteinState {
+        LevenshteinState { distances: (0..=self.query.len() as u32).collect(), pending: Vec::new() }
+    }
+
+    fn accept(&self, state: &LevenshteinState, byte: u8) -> Option<LevenshteinState> {
+        let mut pending = state.pending.clone();
+        pending.push(byte);
+        let c = match str::from_utf8(&pending) {
+            Ok(s) => s.chars().next().unwrap(),
+            Err(e) if e.error_len().is_none() => return Some(LevenshteinState { distances: state.distances.clone(), pending }),
+            Err(_) => return None,
+        };
+        let prev = &state.distances;
+        let mut distances = Vec::with_capacity(prev.len());
+        distances.push(prev[0] + 1);
+        for (j, &q) in self.query.iter().enumerate() {
+            let substitution = prev[j] + if q == c { 0 } else { 1 };
+            distances.push(substitution.min(prev[j + 1] + 1).min(distances[j] + 1));
+        }
+        if *distances.iter().min().unwrap() > self.max_distance {
+            return None;
+        }
+        Some(LevenshteinState { distances, pending: Vec::new() })
+    }
+
+    fn is_match(&self, state: &LevenshteinState) -> bool {
+        state.pending.is_empty() && *state.distances.last().unwrap() <= self.max_distance
+    }
+}
+
+/// Node on the path of the current key of an `FstStream`
+struct Frame<S> {
+    node: FstNode,
+    /// Next transition to follow
+    at: usize,
+    transitions_left: usize,
+    /// Length of the key at the node
+    depth: usize,
+    output: u64,
+    state: S,
+    /// Key ending at the node was considered
+    visited: bool,
+}
+
+impl<S> Frame<S> {
+    fn new(node: FstNode, depth: usize, output: u64, state: S) -> Self {
+        Frame { node, at: node.transitions_ptr, transitions_left: node.num_transitions, depth, output, state, visited: false }
+    }
+}
+
+/// Keys of an `Fst` accepted by a `Matcher` with their outputs, in ascending order
+pub struct FstStream<'a, M: Matcher> {
+    fst: Fst<'a>,
+    matcher: M,
+    key: Vec<u8>,
+    stack: Vec<Frame<M::State>>,
+}
+
+impl<'a, M: Matcher> Iterator for FstStream<'a, M> {
+    type Item = (Vec<u8>, u64);
+
+    fn next(&mut self) -> Option<(Vec<u8>, u64)> {
+        loop {
+            let frame = tryopt!(self.stack.last_mut());
+            self.key.truncate(frame.depth);
+            if !frame.visited {
+                frame.visited = true;
+                if let Some(final_output) = frame.node.final_output {
+                    if self.matcher.is_match(&frame.state) {
+                        return Some((self.key.clone(), frame.output + final_output));
+                    }
+                }
+            }
+            if frame.transitions_left == 0 {
+                self.stack.pop();
+                continue;
+            }
+            let transition = self.fst.transition(&frame.node, &mut frame.at);
+            frame.transitions_left -= 1;
+            if let Some(state) = self.matcher.accept(&frame.state, transition.label) {
+                let output = frame.output + transition.output;
+                self.key.push(transition.label);
+                let child = Frame::new(self.fst.node_at(transition.target), self.key.len(), output, state);
+                self.stack.push(child);
+            }
+        }
+    }
+}
+
+/// Terms of a segment in an `Fst` mapping them to their entries in a table of `TermInfo`s.
+/// Written by `FstDictionaryBuilder`
+#[derive(Clone, Copy)]
+pub struct FstDictionary<'a> {
+    fst: Fst<'a>,
+    infos: &'a [u8],
+}
+
+fn term_info(infos: &[u8], ordinal: u64) -> TermInfo {
+    TermInfo::from_bytes(&infos[ordinal as usize * TermInfo::SIZE..])
+}
+
+impl<'a> FstDictionary<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        let end = bytes.len() - 8;
+        let fst_size = u64::from_le_bytes(bytes[end..].try_into().unwrap()) as usize;
+        FstDictionary { fst: Fst::new(&bytes[..fst_size]), infos: &bytes[fst_size..end] }
+    }
+
+    pub fn fst(&self) -> &Fst<'a> {
+        &self.fst
+    }
+
+    /// Number of terms
+    pub fn len(&self) -> u64 {
+        self.fst.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fst.is_empty()
+    }
+
+    pub fn find_exact(&self, term: &str) -> Option<TermInfo> {
+        self.fst.get(term.as_bytes()).map(|ordinal| term_info(self.infos, ordinal))
+    }
+
+    /// All terms in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (String, TermInfo)> + 'a {
+        self.terms(self.fst.iter())
+    }
+
+    /// Terms starting with `prefix`
+    pub fn prefix(&self, prefix: &str) -> impl Iterator<Item = (String, TermInfo)> + 'a {
+        self.terms(self.fst.prefix(prefix.as_bytes()))
+    }
+
+    /// Terms between the bounds
+    pub fn range(&self, from: Bound<&str>, to: Bound<&str>) -> impl Iterator<Item = (String, TermInfo)> + 'a {
+        self.terms(self.fst.range(from.map(str::as_bytes), to.map(str::as_bytes)))
+    }
+
+    /// Terms within `max_distance` edits of `term`, counting in chars
+    pub fn fuzzy(&self, term: &str, max_distance: u32) -> impl Iterator<Item = (String, TermInfo)> + 'a {
+        self.terms(self.fst.fuzzy(term, max_distance))
+    }
+
+    fn terms<M: Matcher + 'a>(&self, stream: FstStream<'a, M>) -> impl Iterator<Item = (String, TermInfo)> + 'a {
+        let infos = self.infos;
+        // Terms are inserted as str
+        stream.map(move |(key, ordinal)| (String::from_utf8(key).unwrap(), term_info(infos, ordinal)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use random::XorShift;
+    use fst::{Fst,FstBuilder,FstDictionary,FstDictionaryBuilder,TermInfo};
+
+    fn build_fst(map: &BTreeMap<String, u64>) -> Vec<u8> {
+        let mut builder = FstBuilder::new();
+        for (key, &output) in map {
+            builder.insert(key.as_bytes(), output);
+        }
+        builder.finish()
+    }
+
+    fn levenshtein(a: &str, b: &str) -> u32 {
+        let b = b.chars().collect::<Vec<_>>();
+        let mut row = (0..=b.len() as u32).collect::<Vec<_>>();
+        for c in a.chars() {
+            let mut next = vec![row[0] + 1];
+            for j in 0..b.len() {
+                next.push((row[j] + (b[j] != c) as u32).min(row[j + 1] + 1).min(next[j] + 1));
+            }
+            row = next;
+        }
+        row[b.len()]
+    }
+
+    fn random_word(rng: &mut XorShift) -> String {
+        let alphabet = ['a', 'b', 'c', 'ä', '木'];
+        (0..rng.below(6)).map(|_| alphabet[rng.below(alphabet.len() as u64) as usize]).collect()
+    }
+
+    fn bound<'s>(rng: &mut XorShift, word: &'s str) -> Bound<&'s str> {
+        match rng.below(3) {
+            0 => Bound::Included(word),
+            1 => Bound::Excluded(word),
+            _ => Bound::Unbounded,
+        }
+    }
+
+    fn in_bounds(key: &str, from: Bound<&str>, to: Bound<&str>) -> bool {
+        (match from {
+            Bound::Included(from) => key >= from,
+            Bound::Excluded(from) => key > from,
+            Bound::Unbounded => true,
+        }) && (match to {
+            Bound::Included(to) => key <= to,
+            Bound::Excluded(to) => key < to,
+            Bound::Unbounded => true,
+        })
+    }
+
+    fn owned(stream: impl Iterator<Item = (Vec<u8>, u64)>) -> Vec<(String, u64)> {
+        stream.map(|(key, output)| (String::from_utf8(key).unwrap(), output)).collect()
+    }
+
+    #[test]
+    fn test_lookups() {
+        let mut rng = XorShift::new(1);
+        for _ in 0..50 {
+            let map = (0..rng.below(60)).map(|_| (random_word(&mut rng), rng.below(1000))).collect::<BTreeMap<_, _>>();
+            let bytes = build_fst(&map);
+            let fst = Fst::new(&bytes);
+            assert_eq!(fst.len(), map.len() as u64);
+            assert_eq!(owned(fst.iter()), map.iter().map(|(k, &v)| (k.clone(), v)).collect::<Vec<_>>());
+
+            for _ in 0..20 {
+                let query = random_word(&mut rng);
+                assert_eq!(fst.get(query.as_bytes()), map.get(&query).cloned(), "{}", query);
+
+                let expected = map.iter().filter(|&(k, _)| k.starts_with(&query)).map(|(k, &v)| (k.clone(), v)).collect::<Vec<_>>();
+                assert_eq!(owned(fst.prefix(query.as_bytes())), expected, "{}*", query);
+
+                let other = random_word(&mut rng);
+                let (from, to) = (bound(&mut rng, &query), bound(&mut rng, &other));
+                let expected = map.iter().filter(|&(k, _)| in_bounds(k, from, to)).map(|(k, &v)| (k.clone(), v)).collect::<Vec<_>>();
+                assert_eq!(owned(fst.range(from.map(str::as_bytes), to.map(str::as_bytes))), expected, "{:?}..{:?}", from, to);
+
+                let max_distance = rng.below(3) as u32;
+                let expected = map.iter().filter(|&(k, _)| levenshtein(k, &query) <= max_distance).map(|(k, &v)| (k.clone(), v)).collect::<Vec<_>>();
+                assert_eq!(owned(fst.fuzzy(&query, max_distance)), expected, "{}~{}", query, max_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_suffixes() {
+        let map = (b'a'..=b'z').map(|c| (format!("{}ing", c as char), 7)).collect::<BTreeMap<_, _>>();
+        let bytes = build_fst(&map);
+        // Root and a single chain for all the suffixes
+        assert!(bytes.len() < 26 * 4 + 32, "{}", bytes.len());
+        assert_eq!(Fst::new(&bytes).get(b"king"), Some(7));
+        assert_eq!(Fst::new(&bytes).get(b"kin"), None);
+
+        // Empty key and a key with all the others as its extensions
+        let map = vec![("".to_string(), 3), ("a".to_string(), 1), ("ab".to_string(), 2)].into_iter().collect();
+        let bytes = build_fst(&map);
+        assert_eq!(owned(Fst::new(&bytes).iter()), vec![("".to_string(), 3), ("a".to_string(), 1), ("ab".to_string(), 2)]);
+        assert!(Fst::new(&build_fst(&BTreeMap::new())).iter().next().is_none());
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let terms = ["apple", "apply", "banana", "band", "bandana"];
+        let mut builder = FstDictionaryBuilder::new();
+        for (i, term) in terms.iter().enumerate() {
+            builder.insert(term, TermInfo { postings_ptr: 10 * i as u64, num_postings: i as u64 + 1, term_id: i as u32 + 1 });
+        }
+        let bytes = builder.finish();
+        let dict = FstDictionary::new(&bytes);
+        assert_eq!(dict.len(), 5);
+        assert_eq!(dict.find_exact("band"), Some(TermInfo { postings_ptr: 30, num_postings: 4, term_id: 4 }));
+        assert_eq!(dict.find_exact("ban"), None);
+        assert_eq!(dict.prefix("ban").map(|(term, _)| term).collect::<Vec<_>>(), vec!["banana", "band", "bandana"]);
+        assert_eq!(dict.range(Bound::Excluded("apply"), Bound::Included("band")).map(|(term, info)| (term, info.term_id)).collect::<Vec<_>>(),
+                   vec![("banana".to_string(), 3), ("band".to_string(), 4)]);
+        assert_eq!(dict.fuzzy("aple", 1).map(|(term, _)| term).collect::<Vec<_>>(), vec!["apple"]);
+        assert_eq!(dict.iter().count(), 5);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use util::write_varint;
+use fst::{TermInfo,FST_FINAL};
+
+/// Node on the path of the last key inserted, its transitions may still grow
+#[derive(Default, Hash, PartialEq, Eq)]
+struct UnfinishedNode {
+    is_final: bool,
+    final_output: u64,
+    /// Transitions to written nodes as (label, output, target pointer)
+    transitions: Vec<(u8, u64, u64)>,
+    /// Transition to the next unfinished node as (label, output)
+    last: Option<(u8, u64)>,
+}
+
+impl UnfinishedNode {
+    /// Add `output` to the outputs of all keys through the node
+    fn add_output(&mut self, output: u64) {
+        if self.is_final {
+            self.final_output += output;
+        }
+        for transition in &mut self.transitions {
+            transition.1 += output;
+        }
+        if let Some((_, ref mut last_output)) = self.last {
+            *last_output += output;
+        }
+    }
+}
+
+/// Build an `Fst` from keys inserted in strictly ascending order. Nodes are written as soon
+/// as no later key can pass through them, equal nodes only once
+pub struct FstBuilder {
+    bytes: Vec<u8>,
+    /// Path of the last key, the root first
+    unfinished: Vec<UnfinishedNode>,
+    last_key: Vec<u8>,
+    /// Pointers of written nodes by their contents
+    registry: HashMap<UnfinishedNode, u64>,
+    len: u64,
+}
+
+impl Default for FstBuilder {
+    fn default() -> Self {
+        FstBuilder {
+            bytes: Vec::new(),
+            unfinished: vec![UnfinishedNode::default()],
+            last_key: Vec::new(),
+            registry: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl FstBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of keys inserted
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, key: &[u8], mut output: u64) {
+        assert!(self.len == 0 || key > &self.last_key[..], "Keys must be inserted in strictly ascending order");
+        let prefix_len = key.iter().zip(&self.last_key).take_while(|&(a, b)| a == b).count();
+        self.freeze(prefix_len + 1);
+        // Keep the smaller output on the shared path and push the rest of the old one down
+        for i in 0..prefix_len {
+            let (label, last_output) = self.unfinished[i].last.unwrap();
+            let common = last_output.min(output);
+            self.unfinished[i].last = Some((label, common));
+            self.unfinished[i + 1].add_output(last_output - common);
+            output -= common;
+        }
+        if prefix_len == key.len() {
+            // Only an empty first key ends at a node already there
+            let node = &mut self.unfinished[prefix_len];
+            node.is_final = true;
+            node.final_output = output;
+        } else {
+            self.unfinished[prefix_len].last = Some((key[prefix_len], output));
+            for &label in &key[prefix_len + 1..] {
+                self.unfinished.push(UnfinishedNode { last: Some((label, 0)), ..Default::default() });
+            }
+            self.unfinished.push(UnfinishedNode { is_final: true, ..Default::default() });
+        }
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.len += 1;
+    }
+
+    /// Write the unfinished nodes but the first `depth`, deepest first
+    fn freeze(&mut self, depth: usize) {
+        while self.unfinished.len() > depth {
+            let node = self.unfinished.pop().unwrap();
+            let ptr = self.write_node(node);
+            let parent = self.unfinished.last_mut().unwrap();
+            let (label, output) = parent.last.take().unwrap();
+            parent.transitions.push((label, output, ptr));
+        }
+    }
+
+    fn write_node(&mut self, node: UnfinishedNode) -> u64 {
+        debug_assert!(node.last.is_none());
+        if let Some(&ptr) = self.registry.get(&node) {
+            return ptr;
+        }
+        let ptr = self.bytes.len() as u64;
+        self.bytes.push(if node.is_final { FST_FINAL } else { 0 });
+        if node.is_final {
+            write_varint(&mut self.bytes, node.final_output);
+        }
+        write_varint(&mut self.bytes, node.transitions.len() as u64);
+        for &(label, output, target) in &node.transitions {
+            self.bytes.push(label);
+            write_varint(&mut self.bytes, output);
+            write_varint(&mut self.bytes, ptr - target);
+        }
+        self.registry.insert(node, ptr);
+        ptr
+    }
+
+    /// Bytes of the `Fst`
+    pub fn finish(mut self) -> Vec<u8> {
+        self.freeze(1);
+        let root = self.unfinished.pop().unwrap();
+        let root_ptr = self.write_node(root);
+        self.bytes.extend_from_slice(&root_ptr.to_le_bytes());
+        self.bytes.extend_from_slice(&self.len.to_le_bytes());
+        self.bytes
+    }
+}
+
+/// Build an `FstDictionary` from terms inserted in strictly ascending order
+#[derive(Default)]
+pub struct FstDictionaryBuilder {
+    fst: FstBuilder,
+    infos: Vec<u8>,
+}
+
+impl FstDictionaryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, term: &str, info: TermInfo) {
+        let ordinal = self.fst.len();
+        self.fst.insert(term.as_bytes(), ordinal);
+        info.write_to(&mut self.infos);
+    }
+
+    /// Bytes of the `Fst` followed by the `TermInfo` table and the size of the `Fst` as u64
+    /// little endian
+    pub fn finish(self) -> Vec<u8> {
+        let mut bytes = self.fst.finish();
+        let fst_size = bytes.len() as u64;
+        bytes.extend_from_slice(&self.infos);
+        bytes.extend_from_slice(&fst_size.to_le_bytes());
+        bytes
+    }
+}
@@ -0,0 +1,174 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::mem;
+use types::*;
+use termbuf::TermBuf;
+
+/// In-memory inverted index of documents added since the last commit
+pub struct Inverter {
+    terms: HashMap<String, TermId>,
+    term_serial: TermId,
+    doc_serial: DocId,
+    docbufs: TermBuf,
+    tfbufs: TermBuf,
+    posbufs: TermBuf,
+    memory_usage: usize,
+}
+
+/// Estimated bytes taken by a term besides its postings: the key and value in the term map
+/// and three postings buffers
+const TERM_OVERHEAD: usize = mem::size_of::<String>() + mem::size_of::<TermId>() + 3 * mem::size_of::<Option<Vec<DocId>>>() + 16;
+
+impl Default for Inverter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inverter {
+    pub fn new() -> Inverter {
+        Inverter {
+            terms: HashMap::new(),
+            term_serial: 0,
+            doc_serial: 0,
+            docbufs: TermBuf::new(),
+            tfbufs: TermBuf::new(),
+            posbufs: TermBuf::new(),
+            memory_usage: 0,
+        }
+    }
+
+    pub fn num_docs(&self) -> DocId {
+        self.doc_serial
+    }
+
+    pub fn max_term_id(&self) -> TermId {
+        self.term_serial
+    }
+
+    /// Estimate of the memory taken by terms and their postings in bytes. Spare capacity of
+    /// buffers is not accounted for
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Move all terms and postings out into a new inverter. Documents added afterwards keep
+    /// their DocIds following the moved ones
+    pub fn split_off(&mut self) -> Inverter {
+        let rest = Inverter {
+            doc_serial: self.doc_serial,
+            ..Inverter::new()
+        };
+        mem::replace(self, rest)
+    }
+
+    /// Id of the term, assigned in order of first occurrence
+    fn term_id(&mut self, term: &str) -> TermId {
+        let term_serial = &mut self.term_serial;
+        let memory_usage = &mut self.memory_usage;
+        *self.terms.entry(term.into()).or_insert_with(|| {
+            *memory_usage += term.len() + TERM_OVERHEAD;
+            *term_serial += 1;
+            *term_serial
+        })
+    }
+
+    /// Append documents of another inverter behind documents of this one. The result is the
+    /// same as if the documents were all added to this inverter, term ids included
+    pub fn append(&mut self, mut other: Inverter) {
+        let base = self.doc_serial;
+        let mut terms = other.terms.drain().collect::<Vec<_>>();
+        terms.sort_by_key(|&(_, term_id)| term_id);
+        for (term, other_id) in terms {
+            let term_id = self.term_id(&term);
+            let docs = other.docbufs.get_termbuf(other_id).unwrap();
+            let tfs = other.tfbufs.get_termbuf(other_id).unwrap();
+            let positions = other.posbufs.get_termbuf(other_id).unwrap();
+            self.memory_usage += mem::size_of::<DocId>() * (docs.len() + tfs.len() + positions.len());
+            for (doc_id, tf) in docs.into_iter().zip(tfs) {
+                self.docbufs.add_doc(term_id, base + doc_id);
+                self.tfbufs.add_doc(term_id, tf);
+            }
+            for position in positions {
+                self.posbufs.add_doc(term_id, position);
+            }
+        }
+        self.doc_serial += other.doc_serial;
+    }
+
+    /// Add a document made of terms at their positions. Empty terms and terms containing
+    /// '\0', which terminates terms in the trie, are skipped with a warning, but still take
+    /// up a position. Returns segment local id of the document or None if the document has
+    /// no terms
+    pub fn add_document<S: AsRef<str>>(&mut self, fields: &[S]) -> Option<DocId> {
+        let mut forward_index = Vec::<(TermId, DocId)>::new();
+        for (position, s) in fields.iter().map(|s| s.as_ref()).enumerate() {
+            if s.contains('\0') {
+                warn!("Skipping term {:?} containing '\\0' at position {}", s, position);
+            } else if !s.is_empty() {
+                let term_id = self.term_id(s);
+                forward_index.push((term_id, position as DocId));
+            }
+        }
+
+        // Skip empty documents
+        if forward_index.is_empty() {
+            return None;
+        }
+
+        self.doc_serial += 1;
+        let doc_id = self.doc_serial;
+        forward_index.sort_by(|a, b| {
+            let c = a.0.cmp(&b.0);
+            if c == Ordering::Equal {
+                return a.1.cmp(&b.1);
+            }
+            c
+        });
+
+        // Checksum for tfs. All positions must sum to this
+        let mut control_tf = 0;
+        // forward_index will be moved to loop
+        let len = forward_index.len();
+
+        let mut last_term_id = 0;
+        let mut tf = 0;
+
+        let mut forward_terms = 0;
+        macro_rules! ADD_DOC {
+            () => {
+                forward_terms += 1;
+                self.docbufs.add_doc(last_term_id, doc_id);
+                self.tfbufs.add_doc(last_term_id, tf);
+                assert!(tf > 0);
+                control_tf += tf;
+            }
+        }
+
+        for (term_id, position) in forward_index {
+            self.posbufs.add_doc(term_id, position);
+            if term_id == last_term_id {
+                tf += 1;
+            } else {
+                if last_term_id != 0 {
+                    ADD_DOC!();
+                }
+                last_term_id = term_id;
+                tf = 1;
+            }
+        }
+        ADD_DOC!();
+        assert_eq!(control_tf as usize, len);
+        // Every term of the document took a doc and a tf, every position a position
+        self.memory_usage += mem::size_of::<DocId>() * (2 * forward_terms + len);
+
+        Some(doc_id)
+    }
+
+    /// Drain terms sorted lexicographically together with their postings buffers
+    pub fn finish(mut self) -> (Vec<Term>, (TermBuf, TermBuf, TermBuf)) {
+        let mut ts: Vec<Term> = self.terms.drain().map(|(term, term_id)| Term {term, term_id}).collect();
+        ts.sort_by(|a, b| a.term.cmp(&b.term));
+        (ts, (self.docbufs, self.tfbufs, self.posbufs))
+    }
+}
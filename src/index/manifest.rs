@@ -0,0 +1,150 @@
+use std::fs::{self,File};
+use std::io::{self,BufRead,BufReader,BufWriter,Write};
+use std::path::Path;
+use types::*;
+
+pub static MANIFEST_FILE: &str = "segments";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentInfo {
+    /// Name of the segment's directory within the index directory
+    pub name: String,
+    /// Number of documents in the segment. Documents are numbered 1..=num_docs
+    pub num_docs: DocId,
+    /// Generation of the segment's deletes file, 0 when no document was deleted
+    pub del_gen: u64,
+    pub num_deleted: DocId,
+}
+
+impl SegmentInfo {
+    pub fn new(name: String, num_docs: DocId) -> Self {
+        SegmentInfo {
+            name,
+            num_docs,
+            del_gen: 0,
+            num_deleted: 0,
+        }
+    }
+
+    pub fn num_live_docs(&self) -> DocId {
+        self.num_docs - self.num_deleted
+    }
+}
+
+/// List of live segments of an index. Stored as a text file `segments` in the index
+/// directory. Every change of the list or of segments' deletions bumps the generation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub generation: u64,
+    pub segments: Vec<SegmentInfo>,
+}
+
+fn invalid(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid segments manifest line: '{}'", line))
+}
+
+impl Manifest {
+    /// Read manifest of the index. Missing manifest means empty index
+    pub fn read(directory: &Path) -> io::Result<Manifest> {
+        let path = directory.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+
+        let mut manifest = Manifest::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["generation", generation] => {
+                    manifest.generation = generation.parse().map_err(|_| invalid(&line))?;
+                }
+                ["segment", name, num_docs] => {
+                    let num_docs = num_docs.parse().map_err(|_| invalid(&line))?;
+                    manifest.segments.push(SegmentInfo::new(name.to_string(), num_docs));
+                }
+                ["segment", name, num_docs, del_gen, num_deleted] => {
+                    manifest.segments.push(SegmentInfo {
+                        name: name.to_string(),
+                        num_docs: num_docs.parse().map_err(|_| invalid(&line))?,
+                        del_gen: del_gen.parse().map_err(|_| invalid(&line))?,
+                        num_deleted: num_deleted.parse().map_err(|_| invalid(&line))?,
+                    });
+                }
+                [] => {}
+                _ => return Err(invalid(&line)),
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Atomically replace the manifest of the index
+    pub fn write(&self, directory: &Path) -> io::Result<()> {
+        let tmp_path = directory.join(format!("{}.tmp", MANIFEST_FILE));
+        {
+            let mut out = BufWriter::new(File::create(&tmp_path)?);
+            writeln!(out, "generation {}", self.generation)?;
+            for segment in &self.segments {
+                if segment.del_gen > 0 {
+                    writeln!(out, "segment {} {} {} {}", segment.name, segment.num_docs, segment.del_gen, segment.num_deleted)?;
+                } else {
+                    writeln!(out, "segment {} {}", segment.name, segment.num_docs)?;
+                }
+            }
+            out.into_inner()?.sync_all()?;
+        }
+        fs::rename(tmp_path, directory.join(MANIFEST_FILE))
+    }
+
+    /// Name for a new segment. Unique because generations only grow
+    pub fn next_segment_name(&self) -> String {
+        format!("seg_{:06}", self.generation + 1)
+    }
+
+    /// DocId offset of every segment. Global DocId is offset + segment local DocId
+    pub fn doc_offsets(&self) -> Vec<DocId> {
+        let mut offset = 0;
+        self.segments.iter().map(|s| {
+            let base = offset;
+            offset += s.num_docs;
+            base
+        }).collect()
+    }
+
+    /// Size of the global DocId space, deleted documents included
+    pub fn num_docs(&self) -> DocId {
+        self.segments.iter().map(|s| s.num_docs).sum()
+    }
+
+    pub fn num_deleted(&self) -> DocId {
+        self.segments.iter().map(|s| s.num_deleted).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::TempDir;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let dir = TempDir::new("manifest");
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(Manifest::read(&dir).unwrap(), Manifest::default());
+
+        let manifest = Manifest {
+            generation: 3,
+            segments: vec![
+                SegmentInfo::new("seg_000001".into(), 10),
+                SegmentInfo { name: "seg_000003".into(), num_docs: 5, del_gen: 3, num_deleted: 2 },
+            ],
+        };
+        manifest.write(&dir).unwrap();
+        assert_eq!(Manifest::read(&dir).unwrap(), manifest);
+        assert_eq!(manifest.doc_offsets(), vec![0, 10]);
+        assert_eq!(manifest.num_docs(), 15);
+        assert_eq!(manifest.num_deleted(), 2);
+        assert_eq!(manifest.next_segment_name(), "seg_000004");
+    }
+}
@@ -0,0 +1,201 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::path::Path;
+use types::*;
+use nutrie::{TrieOptions,TermStats};
+use dictionary::{TermDictionary,PostingsList};
+use bitmap::Bitmap;
+use postings::{PostingsCursor,RawCursor,Merge,Remap,Filter,decode_positions};
+use index::{Segment,write_segment_terms,write_keys};
+
+/// Merges terms of several dictionaries in lexicographic order. Yields every term once
+/// together with its postings in the dictionaries containing it
+struct TermMerger<I: Iterator<Item = (String, PostingsList)>> {
+    dicts: Vec<I>,
+    heads: Vec<Option<PostingsList>>,
+    frontier: BinaryHeap<Reverse<(String, usize)>>,
+}
+
+impl<I: Iterator<Item = (String, PostingsList)>> TermMerger<I> {
+    fn new(dicts: Vec<I>) -> Self {
+        let mut merger = TermMerger {
+            heads: vec![None; dicts.len()],
+            dicts,
+            frontier: BinaryHeap::new(),
+        };
+        for i in 0..merger.dicts.len() {
+            merger.pull(i);
+        }
+        merger
+    }
+
+    fn pull(&mut self, i: usize) {
+        if let Some((term, list)) = self.dicts[i].next() {
+            self.heads[i] = Some(list);
+            self.frontier.push(Reverse((term, i)));
+        }
+    }
+}
+
+impl<I: Iterator<Item = (String, PostingsList)>> Iterator for TermMerger<I> {
+    type Item = (String, Vec<(usize, PostingsList)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((term, i)) = tryopt!(self.frontier.pop());
+        let mut found = vec![(i, self.heads[i].take().unwrap())];
+        self.pull(i);
+        while self.frontier.peek().is_some_and(|&Reverse((ref next, _))| *next == term) {
+            let Reverse((_, j)) = self.frontier.pop().unwrap();
+            found.push((j, self.heads[j].take().unwrap()));
+            self.pull(j);
+        }
+        found.sort_by_key(|&(i, _)| i);
+        Some((term, found))
+    }
+}
+
+/// New DocIds of a segment with its deleted documents dropped. Deleted documents share DocId
+/// with the preceding live document
+fn compaction_table(num_docs: DocId, deleted: &Bitmap) -> Vec<DocId> {
+    let mut new_doc = 0;
+    (0..=num_docs).map(|doc_id| {
+        if doc_id > 0 && !deleted.contains(doc_id) {
+            new_doc += 1;
+        }
+        new_doc
+    }).collect()
+}
+
+/// Merge segments into a new segment written into the directory. Deleted documents are
+/// dropped and DocIds of every segment are shifted behind DocIds of the segments preceding it.
+/// Inner trie nodes get their merged postings rebuilt by `create_trie`. Returns number of
+/// documents in the new segment. Nothing is written when all documents are deleted
+pub fn merge_segments(segments: &[Segment], directory: &Path, options: TrieOptions) -> io::Result<DocId> {
+    let mut offsets = Vec::with_capacity(segments.len());
+    let mut num_docs = 0;
+    for segment in segments {
+        offsets.push(num_docs);
+        num_docs += segment.info.num_live_docs();
+    }
+    if num_docs == 0 {
+        info!("All {} docs of the merged segments are deleted", segments.iter().map(|s| s.info.num_docs).sum::<DocId>());
+        return Ok(0);
+    }
+    let tables = segments.iter().map(|s| {
+        if s.deleted().is_empty() {
+            None
+        } else {
+            Some(compaction_table(s.info.num_docs, s.deleted()))
+        }
+    }).collect::<Vec<_>>();
+
+    let dicts = segments.iter().map(|s| s.dict()).collect::<Vec<_>>();
+    // Upper bound of the terms. Terms are renumbered in lexicographic order
+    let mut stats = TermStats::default();
+    for dict in &dicts {
+        stats.add(&TermStats::of(dict.terms().map(|(term, _)| term)));
+    }
+    let term_serial = stats.num_terms as TermId;
+
+    let mut term_id = 0;
+    let terms = TermMerger::new(dicts.iter().map(|d| d.terms()).collect()).filter_map(|(term, lists)| {
+        let cursors = lists.into_iter().map(|(i, list)| {
+            let cursor = Filter::new(RawCursor::new(segments[i].list_postings(&list)), segments[i].deleted());
+            match tables[i] {
+                Some(ref table) => Remap::with_table(cursor, offsets[i], table),
+                None => Remap::new(cursor, offsets[i]),
+            }
+        }).collect();
+        let mut postings = Merge::new(cursors).collect();
+        // Terms of deleted documents only are gone
+        if postings.docs.is_empty() {
+            return None;
+        }
+        decode_positions(&mut postings);
+        term_id += 1;
+        Some((Term { term, term_id }, postings))
+    });
+
+    info!("Merging {} segments with {} live docs", segments.len(), num_docs);
+    write_segment_terms(directory, term_serial, terms, options, &stats)?;
+
+    let mut keys = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        for (doc_id, key) in segment.keys()? {
+            if !segment.deleted().contains(doc_id) {
+                let new_doc = tables[i].as_ref().map_or(doc_id, |table| table[doc_id as usize]);
+                keys.push((offsets[i] + new_doc, key));
+            }
+        }
+    }
+    write_keys(directory, &keys)?;
+    Ok(num_docs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use index::{NoMergePolicy,LogMergePolicy};
+    use search::Searcher;
+    use test_support::{TempDir,DOCS};
+
+    fn read(dir: &Path, segment: &str, file: &str) -> Vec<u8> {
+        fs::read(dir.join(segment).join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_merged_equals_single_segment() {
+        let single_dir = TempDir::new("merge-single");
+        let mut single = single_dir.writer();
+        for doc in DOCS {
+            single.add_document(doc).unwrap();
+        }
+        single.commit().unwrap();
+
+        let merged_dir = TempDir::new("merge-merged");
+        let mut merged = merged_dir.writer();
+        merged.set_merge_policy(NoMergePolicy);
+        for chunk in DOCS.chunks(2) {
+            for doc in chunk {
+                merged.add_document(doc).unwrap();
+            }
+            merged.commit().unwrap();
+        }
+        assert_eq!(merged.manifest().segments.len(), 3);
+        merged.force_merge().unwrap();
+        assert_eq!(merged.manifest().segments.len(), 1);
+        assert_eq!(merged.manifest().num_docs(), DOCS.len() as u64);
+        assert_eq!(fs::read_dir(&merged_dir).unwrap().count(), 2);
+
+        // Postings of terms and of all inner nodes come out the same
+        let single_name = &single.manifest().segments[0].name;
+        let merged_name = &merged.manifest().segments[0].name;
+        for file in &["docs", "tfs", "positions"] {
+            assert_eq!(read(&single_dir, single_name, file), read(&merged_dir, merged_name, file));
+        }
+
+        let searcher = Searcher::open(&merged_dir).unwrap();
+        assert_eq!(searcher.search(&["apple"], true, None), vec![1, 2, 4]);
+        assert_eq!(searcher.search(&["ban"], false, None), vec![1, 3, 4, 5, 6]);
+        assert_eq!(searcher.search(&["kiwi", "ban"], true, None), vec![5]);
+    }
+
+    #[test]
+    fn test_merge_policy_on_commit() {
+        let dir = TempDir::new("merge-policy");
+        let mut writer = dir.writer();
+        writer.set_merge_policy(LogMergePolicy { merge_factor: 3, min_merge_docs: 2, ..Default::default() });
+        for doc in DOCS {
+            writer.add_document(doc).unwrap();
+            writer.commit().unwrap();
+        }
+        // 6 single doc segments: two merges into segments of 3 docs
+        let sizes = writer.manifest().segments.iter().map(|s| s.num_docs).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![3, 3]);
+
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["banana"], true, None), vec![1, 3, 4, 6]);
+    }
+}
@@ -0,0 +1,15 @@
+pub use self::inverter::*;
+pub use self::manifest::*;
+pub use self::merger::*;
+pub use self::policy::*;
+pub use self::segment::*;
+pub use self::spill::*;
+pub use self::writer::*;
+
+pub mod inverter;
+pub mod manifest;
+pub mod merger;
+pub mod policy;
+pub mod segment;
+pub mod spill;
+pub mod writer;
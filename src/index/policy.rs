@@ -0,0 +1,117 @@
+use std::ops::Range;
+use types::*;
+use index::SegmentInfo;
+
+/// Decides which segments should be merged together
+pub trait MergePolicy {
+    /// Ranges of adjacent segments to merge. Ranges must not overlap. Only adjacent segments
+    /// are merged, so that documents keep their order in the index
+    fn find_merges(&self, segments: &[SegmentInfo]) -> Vec<Range<usize>>;
+}
+
+/// Never merges
+pub struct NoMergePolicy;
+
+impl MergePolicy for NoMergePolicy {
+    fn find_merges(&self, _segments: &[SegmentInfo]) -> Vec<Range<usize>> {
+        Vec::new()
+    }
+}
+
+/// Log-structured merging. Segments fall into levels by the logarithm of their size in base
+/// `merge_factor`. Whenever `merge_factor` adjacent segments share a level they are merged
+/// into a single segment of the next level. Every document thus gets merged about
+/// log(num_docs) times. Sizes count live documents only. A segment left out of such merges
+/// is merged alone once more than `max_deleted_ratio` of its documents are deleted, which
+/// drops the deleted documents for good.
+pub struct LogMergePolicy {
+    pub merge_factor: usize,
+    /// Segments smaller than this all belong to the lowest level
+    pub min_merge_docs: DocId,
+    pub max_deleted_ratio: f64,
+}
+
+impl Default for LogMergePolicy {
+    fn default() -> Self {
+        LogMergePolicy {
+            merge_factor: 10,
+            min_merge_docs: 1000,
+            max_deleted_ratio: 0.3,
+        }
+    }
+}
+
+impl LogMergePolicy {
+    pub fn level(&self, num_docs: DocId) -> u32 {
+        let factor = self.merge_factor as DocId;
+        let mut level = 0;
+        let mut size = self.min_merge_docs.max(1);
+        while num_docs >= size {
+            level += 1;
+            size = size.saturating_mul(factor);
+            if size == DocId::MAX {
+                break;
+            }
+        }
+        level
+    }
+}
+
+impl MergePolicy for LogMergePolicy {
+    fn find_merges(&self, segments: &[SegmentInfo]) -> Vec<Range<usize>> {
+        assert!(self.merge_factor >= 2);
+        let mut merges = Vec::new();
+        let mut start = 0;
+        while start < segments.len() {
+            let level = self.level(segments[start].num_live_docs());
+            let mut end = start + 1;
+            while end < segments.len() && end - start < self.merge_factor && self.level(segments[end].num_live_docs()) == level {
+                end += 1;
+            }
+            if end - start == self.merge_factor {
+                merges.push(start..end);
+            } else {
+                for (i, segment) in segments.iter().enumerate().take(end).skip(start) {
+                    if segment.num_deleted as f64 > segment.num_docs as f64 * self.max_deleted_ratio {
+                        merges.push(i..i + 1);
+                    }
+                }
+            }
+            start = end;
+        }
+        merges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(sizes: &[DocId]) -> Vec<SegmentInfo> {
+        sizes.iter().enumerate().map(|(i, &num_docs)| SegmentInfo::new(format!("seg_{}", i), num_docs)).collect()
+    }
+
+    #[test]
+    fn test_log_merge_policy() {
+        let policy = LogMergePolicy { merge_factor: 3, min_merge_docs: 10, ..Default::default() };
+        assert_eq!(policy.level(0), 0);
+        assert_eq!(policy.level(9), 0);
+        assert_eq!(policy.level(10), 1);
+        assert_eq!(policy.level(29), 1);
+        assert_eq!(policy.level(30), 2);
+
+        assert!(policy.find_merges(&segments(&[5, 5])).is_empty());
+        assert_eq!(policy.find_merges(&segments(&[5, 5, 5])), vec![0..3]);
+        assert_eq!(policy.find_merges(&segments(&[100, 5, 5, 5, 5])), vec![1..4]);
+        assert_eq!(policy.find_merges(&segments(&[15, 12, 20, 1, 2, 3])), vec![0..3, 3..6]);
+        assert!(policy.find_merges(&segments(&[15, 1, 12, 2, 20])).is_empty());
+
+        let mut deleted = segments(&[100, 15, 12, 20]);
+        deleted[0].num_deleted = 60;
+        deleted[1].num_deleted = 5;
+        assert_eq!(policy.find_merges(&deleted), vec![0..1, 1..4]);
+        deleted[0].num_deleted = 10;
+        deleted[1].num_deleted = 14;
+        assert_eq!(policy.find_merges(&deleted), vec![1..2]);
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs::{self,File};
+use std::io::{self,BufRead,BufReader,BufWriter,Read,Write};
+use std::ops::Range;
+use std::path::{Path,PathBuf};
+use types::*;
+use util::bytes_to_typed;
+use bitmap::Bitmap;
+use meta::IndexMeta;
+use nutrie::{StaticTrie,TrieMode,TrieFormat,TrieOptions,TermStats,PostingsEncoders,create_trie,terms_with_postings};
+use fst::{FstDictionary,FstDictionaryBuilder,TermInfo};
+use postings::{Postings,VecPostings,PlainEncoder,Sequence,SequenceStorage,SliceSequence};
+use dictionary::{TermDictionary,PostingsList};
+use index::{Inverter,SegmentInfo};
+
+pub type SegmentPostings<'a> = Postings<SliceSequence<'a>, SliceSequence<'a>, SliceSequence<'a>>;
+
+fn create_writer(directory: &Path, filename: &str) -> io::Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(directory.join(filename))?))
+}
+
+fn read_file(directory: &Path, filename: &str) -> io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(File::open(directory.join(filename))?);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Write inverted documents as a segment into its own directory
+pub fn write_segment(directory: &Path, inverter: Inverter, options: TrieOptions) -> io::Result<()> {
+    assert!(inverter.num_docs() > 0, "Segment must not be empty");
+    let term_serial = inverter.max_term_id();
+    let (terms, (mut docbufs, mut tfbufs, mut posbufs)) = inverter.finish();
+    let stats = TermStats::of(terms.iter().map(|t| &t.term));
+    let mut postings = (&mut docbufs, &mut tfbufs, &mut posbufs);
+    write_segment_terms(directory, term_serial, terms_with_postings(&terms, &mut postings), options, &stats)
+}
+
+/// Write sorted terms with their postings as a segment into its own directory. See
+/// `create_trie` for the meaning of `term_serial`. The trie format is chosen by `stats`, an
+/// upper bound of the terms
+pub fn write_segment_terms<I>(directory: &Path, term_serial: TermId, terms: I, options: TrieOptions, stats: &TermStats) -> io::Result<()>
+    where I: IntoIterator<Item = (Term, VecPostings)>
+{
+    fs::create_dir_all(directory)?;
+    let (mode, format) = (options.mode, options.format(stats));
+
+    let mut enc = PostingsEncoders {
+        docs: PlainEncoder::new(create_writer(directory, "docs")?),
+        tfs: PlainEncoder::new(create_writer(directory, "tfs")?),
+        positions: PlainEncoder::new(create_writer(directory, "positions")?),
+    };
+    let mut dict_out = create_writer(directory, "dict")?;
+    let (_written_terms, dict_size, root_ptr, terms_size) = create_trie(mode, format, options.prefix_postings, term_serial, terms, &mut dict_out, &mut enc);
+    dict_out.flush()?;
+    enc.docs.into_inner().flush()?;
+    enc.tfs.into_inner().flush()?;
+    enc.positions.into_inner().flush()?;
+    if options.fst {
+        write_fst_dictionary(directory, &StaticTrie::new(&read_file(directory, "dict")?, dict_size, root_ptr, terms_size)
+            .with_mode(mode)
+            .with_format(format))?;
+    }
+
+    let meta = IndexMeta {
+        dict_size: dict_size as u64,
+        root_ptr: root_ptr as u64,
+        term_buffer_size: terms_size as u64,
+        docs_size: 0,
+        tfs_size: 0,
+        positions_size: 0,
+        flags: if mode == TrieMode::Bytes { IndexMeta::BYTE_TRIE } else { 0 }
+            | match format {
+                TrieFormat::Narrow => 0,
+                TrieFormat::Wide => IndexMeta::WIDE_TRIE,
+                TrieFormat::Compact => IndexMeta::COMPACT_TRIE,
+            }
+            | if options.fst { IndexMeta::FST_DICTIONARY } else { 0 },
+    };
+    let mut meta_out = create_writer(directory, "meta")?;
+    meta_out.write_all(meta.to_bytes())?;
+    meta_out.flush()
+}
+
+/// Write the terms of the trie into an `FstDictionary`
+fn write_fst_dictionary(directory: &Path, dict: &StaticTrie) -> io::Result<()> {
+    let mut builder = FstDictionaryBuilder::new();
+    for (term, node) in dict.leaves() {
+        builder.insert(&term, TermInfo { postings_ptr: node.postings_ptr, num_postings: node.num_postings, term_id: node.term_id });
+    }
+    let mut out = create_writer(directory, "fst")?;
+    out.write_all(&builder.finish())?;
+    out.flush()
+}
+
+/// Deletions of a segment are kept apart from it in a file per generation of the deletions.
+/// Segment files themselves are never modified
+pub fn deletes_file_name(del_gen: u64) -> String {
+    format!("deletes_{:06}", del_gen)
+}
+
+/// Read the bitmap of deleted segment local DocIds
+pub fn read_deletes(directory: &Path, del_gen: u64) -> io::Result<Bitmap> {
+    if del_gen == 0 {
+        return Ok(Bitmap::new());
+    }
+    Ok(Bitmap::from_bytes(&read_file(directory, &deletes_file_name(del_gen))?))
+}
+
+pub fn write_deletes(directory: &Path, del_gen: u64, deleted: &Bitmap) -> io::Result<()> {
+    let mut out = create_writer(directory, &deletes_file_name(del_gen))?;
+    out.write_all(&deleted.to_bytes())?;
+    out.into_inner()?.sync_all()
+}
+
+/// Read external keys of the segment's documents as (DocId, key) pairs ascending by DocId.
+/// Documents added without a key have none
+pub fn read_keys(directory: &Path) -> io::Result<Vec<(DocId, String)>> {
+    let path = directory.join("keys");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut keys = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let parsed = line.split_once(' ').and_then(|(doc_id, key)| Some((doc_id.parse().ok()?, key.to_string())));
+        match parsed {
+            Some(entry) => keys.push(entry),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid keys line: '{}'", line))),
+        }
+    }
+    Ok(keys)
+}
+
+/// Write external keys of documents, one `<DocId> <key>` line each. Keys must not contain
+/// line breaks. Nothing is written when there are no keys
+pub fn write_keys(directory: &Path, keys: &[(DocId, String)]) -> io::Result<()> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let mut out = create_writer(directory, "keys")?;
+    for (doc_id, key) in keys {
+        writeln!(out, "{} {}", doc_id, key)?;
+    }
+    out.flush()
+}
+
+/// Immutable segment loaded in memory
+pub struct Segment {
+    pub info: SegmentInfo,
+    /// Offset of the segment's DocIds in the whole index
+    pub base: DocId,
+    directory: PathBuf,
+    meta: Vec<u8>,
+    dict: Vec<u8>,
+    docs: Vec<u8>,
+    tfs: Vec<u8>,
+    positions: Vec<u8>,
+    /// Written with `TrieOptions::fst` only
+    fst: Option<Vec<u8>>,
+    deleted: Bitmap,
+}
+
+impl Segment {
+    pub fn open(directory: &Path, info: SegmentInfo, base: DocId) -> io::Result<Segment> {
+        let meta = read_file(directory, "meta")?;
+        let fst = if IndexMeta::from_bytes(&meta).flags & IndexMeta::FST_DICTIONARY != 0 {
+            Some(read_file(directory, "fst")?)
+        } else {
+            None
+        };
+        Ok(Segment {
+            deleted: read_deletes(directory, info.del_gen)?,
+            info,
+            base,
+            directory: directory.to_path_buf(),
+            meta,
+            dict: read_file(directory, "dict")?,
+            docs: read_file(directory, "docs")?,
+            tfs: read_file(directory, "tfs")?,
+            positions: read_file(directory, "positions")?,
+            fst,
+        })
+    }
+
+    pub fn meta(&self) -> IndexMeta {
+        IndexMeta::from_bytes(&self.meta[..])
+    }
+
+    pub fn trie_mode(&self) -> TrieMode {
+        if self.meta().flags & IndexMeta::BYTE_TRIE != 0 { TrieMode::Bytes } else { TrieMode::Chars }
+    }
+
+    pub fn trie_format(&self) -> TrieFormat {
+        let flags = self.meta().flags;
+        if flags & IndexMeta::COMPACT_TRIE != 0 {
+            TrieFormat::Compact
+        } else if flags & IndexMeta::WIDE_TRIE != 0 {
+            TrieFormat::Wide
+        } else {
+            TrieFormat::Narrow
+        }
+    }
+
+    /// Take `deleted` as the deleted documents instead of the committed ones
+    pub fn with_deleted(mut self, deleted: Bitmap) -> Self {
+        self.info.num_deleted = deleted.len() as DocId;
+        self.deleted = deleted;
+        self
+    }
+
+    /// Segment local DocIds of deleted documents
+    pub fn deleted(&self) -> &Bitmap {
+        &self.deleted
+    }
+
+    /// External keys of the segment's documents, read from disk on every call
+    pub fn keys(&self) -> io::Result<Vec<(DocId, String)>> {
+        read_keys(&self.directory)
+    }
+
+    pub fn dict(&self) -> StaticTrie<'_> {
+        let meta = self.meta();
+        StaticTrie::new(&self.dict[..], meta.dict_size as usize, meta.root_ptr as usize, meta.term_buffer_size as usize)
+            .with_mode(self.trie_mode())
+            .with_format(self.trie_format())
+    }
+
+    /// Terms of the segment in an `Fst`, if written
+    pub fn fst_dict(&self) -> Option<FstDictionary<'_>> {
+        self.fst.as_ref().map(|bytes| FstDictionary::new(bytes))
+    }
+
+    /// Dictionary to look query terms up in: the `FstDictionary` if asked for and written, the
+    /// trie otherwise
+    pub fn term_dict(&self, fst: bool) -> Box<dyn TermDictionary + '_> {
+        match self.fst_dict() {
+            Some(fst_dict) if fst => Box::new(fst_dict),
+            _ => Box::new(self.dict()),
+        }
+    }
+
+    pub fn postings(&self) -> SegmentPostings<'_> {
+        Postings {
+            docs: bytes_to_typed(&self.docs).to_sequence(),
+            tfs: bytes_to_typed(&self.tfs).to_sequence(),
+            positions: bytes_to_typed(&self.positions).to_sequence(),
+        }
+    }
+
+    /// Postings of a single list
+    pub fn list_postings(&self, list: &PostingsList) -> SegmentPostings<'_> {
+        let p = self.postings();
+        let (ptr, len) = (list.postings_ptr as usize, list.num_postings as usize);
+        Postings {
+            docs: p.docs.subsequence(ptr, len),
+            tfs: p.tfs.subsequence(ptr, len + 1),
+            positions: p.positions,
+        }
+    }
+
+    /// Postings of a single list restricted to documents in the DocId range
+    pub fn list_postings_in(&self, list: &PostingsList, doc_ids: Range<DocId>) -> SegmentPostings<'_> {
+        let p = self.postings();
+        let (ptr, len) = (list.postings_ptr as usize, list.num_postings as usize);
+        let docs = &p.docs.as_slice()[ptr..ptr + len];
+        let start = docs.partition_point(|&doc_id| doc_id < doc_ids.start);
+        let end = docs.partition_point(|&doc_id| doc_id < doc_ids.end);
+        Postings {
+            docs: p.docs.subsequence(ptr + start, end - start),
+            tfs: p.tfs.subsequence(ptr + start, end - start + 1),
+            positions: p.positions,
+        }
+    }
+
+    /// Sizes of the stored streams in bytes
+    pub fn stream_sizes(&self) -> Vec<(&'static str, usize)> {
+        let mut sizes = vec![
+            ("dict", self.dict.len()),
+            ("docs", self.docs.len()),
+            ("tfs", self.tfs.len()),
+            ("positions", self.positions.len()),
+        ];
+        if let Some(ref fst) = self.fst {
+            sizes.push(("fst", fst.len()));
+        }
+        sizes
+    }
+}
@@ -0,0 +1,224 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self,BufReader,BufWriter,Read,Write};
+use std::path::{Path,PathBuf};
+use types::*;
+use nutrie::{TrieOptions,TermStats,terms_with_postings};
+use postings::{VecPostings,Frontier,FrontierPointer};
+use index::{Inverter,write_segment_terms};
+
+// Run file layout, all numbers are little endian u64:
+//   num_terms, total length of the terms, maximum term length
+//   per term in lexicographic order: term length, term bytes, num_docs, docs, tfs,
+//   num_positions, positions
+// Tfs are not cumulated and positions are raw, as `create_trie` expects them.
+
+fn write_u64s<W: Write>(out: &mut W, xs: &[DocId]) -> io::Result<()> {
+    for x in xs {
+        out.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u64s<R: Read>(reader: &mut R, n: u64) -> io::Result<Vec<DocId>> {
+    (0..n).map(|_| read_u64(reader)).collect()
+}
+
+/// Write terms of the inverter with their postings as a sorted run
+pub fn write_run(path: &Path, inverter: Inverter) -> io::Result<()> {
+    let (terms, (mut docbufs, mut tfbufs, mut posbufs)) = inverter.finish();
+    let mut postings = (&mut docbufs, &mut tfbufs, &mut posbufs);
+    let mut out = BufWriter::new(File::create(path)?);
+    let stats = TermStats::of(terms.iter().map(|t| &t.term));
+    write_u64s(&mut out, &[stats.num_terms, stats.term_bytes, stats.max_term_len])?;
+    for (term, postings) in terms_with_postings(&terms, &mut postings) {
+        write_u64s(&mut out, &[term.term.len() as u64])?;
+        out.write_all(term.term.as_bytes())?;
+        write_u64s(&mut out, &[postings.docs.len() as u64])?;
+        write_u64s(&mut out, &postings.docs)?;
+        write_u64s(&mut out, &postings.tfs)?;
+        write_u64s(&mut out, &[postings.positions.len() as u64])?;
+        write_u64s(&mut out, &postings.positions)?;
+    }
+    out.flush()
+}
+
+/// Reads a run term by term
+struct RunReader {
+    reader: BufReader<File>,
+    stats: TermStats,
+    remaining: u64,
+    /// Order of the run. Runs hold consecutive ranges of DocIds
+    index: usize,
+    head: Option<(String, VecPostings)>,
+}
+
+impl RunReader {
+    fn open(path: &Path, index: usize) -> io::Result<RunReader> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let stats = TermStats {
+            num_terms: read_u64(&mut reader)?,
+            term_bytes: read_u64(&mut reader)?,
+            max_term_len: read_u64(&mut reader)?,
+        };
+        Ok(RunReader {
+            reader,
+            stats,
+            remaining: stats.num_terms,
+            index,
+            head: None,
+        })
+    }
+
+    /// Read the next term into the head. Returns false at the end of the run
+    fn pull(&mut self) -> io::Result<bool> {
+        if self.remaining == 0 {
+            self.head = None;
+            return Ok(false);
+        }
+        self.remaining -= 1;
+
+        let r = &mut self.reader;
+        let term_len = read_u64(r)? as usize;
+        let mut term = vec![0; term_len];
+        r.read_exact(&mut term)?;
+        let term = String::from_utf8(term).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let num_docs = read_u64(r)?;
+        let docs = read_u64s(r, num_docs)?;
+        let tfs = read_u64s(r, num_docs)?;
+        let num_positions = read_u64(r)?;
+        let positions = read_u64s(r, num_positions)?;
+        self.head = Some((term, VecPostings { docs, tfs, positions }));
+        Ok(true)
+    }
+
+    fn term(&self) -> &str {
+        &self.head.as_ref().unwrap().0
+    }
+}
+
+impl Frontier for RunReader {
+    fn cmp_current(&self, other: &Self) -> Ordering {
+        self.term().cmp(other.term()).then(self.index.cmp(&other.index))
+    }
+}
+
+/// K-way merge of runs. Yields every term once with postings of all runs concatenated.
+/// Terms get ids in lexicographic order. Stops on the first read error and keeps it
+struct RunMerger {
+    frontier: BinaryHeap<FrontierPointer<RunReader>>,
+    term_id: TermId,
+    error: Option<io::Error>,
+}
+
+impl RunMerger {
+    fn advance(&mut self, mut ptr: FrontierPointer<RunReader>) {
+        match ptr.cursor.pull() {
+            Ok(true) => self.frontier.push(ptr),
+            Ok(false) => {}
+            Err(e) => {
+                self.error = Some(e);
+                self.frontier.clear();
+            }
+        }
+    }
+}
+
+impl Iterator for RunMerger {
+    type Item = (Term, VecPostings);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut ptr = tryopt!(self.frontier.pop());
+        let (term, mut postings) = ptr.cursor.head.take().unwrap();
+        self.advance(ptr);
+        while self.frontier.peek().is_some_and(|next| next.cursor.term() == term) {
+            let mut ptr = self.frontier.pop().unwrap();
+            let (_, next) = ptr.cursor.head.take().unwrap();
+            postings.docs.extend(next.docs);
+            postings.tfs.extend(next.tfs);
+            postings.positions.extend(next.positions);
+            self.advance(ptr);
+        }
+        self.term_id += 1;
+        Some((Term { term, term_id: self.term_id }, postings))
+    }
+}
+
+/// Merge sorted runs, given in DocId order, into a segment written into the directory
+pub fn write_segment_runs(directory: &Path, runs: &[PathBuf], options: TrieOptions) -> io::Result<()> {
+    let mut frontier = BinaryHeap::new();
+    let mut stats = TermStats::default();
+    for (index, path) in runs.iter().enumerate() {
+        let mut reader = RunReader::open(path, index)?;
+        // Upper bound of the terms
+        stats.add(&reader.stats);
+        if reader.pull()? {
+            frontier.push(FrontierPointer { cursor: reader });
+        }
+    }
+
+    info!("Merging {} runs into segment {}", runs.len(), directory.display());
+    let mut merger = RunMerger {
+        frontier,
+        term_id: 0,
+        error: None,
+    };
+    write_segment_terms(directory, stats.num_terms as TermId, &mut merger, options, &stats)?;
+    match merger.error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use index::Inverter;
+    use search::Searcher;
+    use test_support::{TempDir,DOCS};
+
+    #[test]
+    fn test_split_off() {
+        let mut inverter = Inverter::new();
+        inverter.add_document(&["apple", "banana"]);
+        assert!(inverter.memory_usage() > 0);
+        let first = inverter.split_off();
+        assert_eq!(first.num_docs(), 1);
+        assert_eq!(inverter.memory_usage(), 0);
+        assert_eq!(inverter.add_document(&["apple"]), Some(2));
+    }
+
+    #[test]
+    fn test_spilled_equals_in_memory() {
+        let mut dirs = Vec::new();
+        for &budget in &[None, Some(1), Some(300)] {
+            let dir = TempDir::new(&format!("spill-{:?}", budget));
+            let mut writer = dir.writer();
+            writer.set_memory_budget(budget);
+            for doc in DOCS {
+                writer.add_document(doc).unwrap();
+            }
+            writer.commit().unwrap();
+            assert!(!dir.join("runs").exists());
+            dirs.push((dir, writer.manifest().segments[0].name.clone()));
+        }
+
+        let (ref first_dir, ref first_name) = dirs[0];
+        for (dir, name) in &dirs[1..] {
+            for file in &["docs", "tfs", "positions"] {
+                assert_eq!(fs::read(first_dir.join(first_name).join(file)).unwrap(),
+                           fs::read(dir.join(name).join(file)).unwrap());
+            }
+            let searcher = Searcher::open(dir).unwrap();
+            assert_eq!(searcher.search(&["apple"], true, None), vec![1, 2, 4]);
+            assert_eq!(searcher.search(&["ban"], false, None), vec![1, 3, 4, 5, 6]);
+        }
+    }
+}
@@ -0,0 +1,652 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path,PathBuf};
+use std::thread;
+use types::*;
+use bitmap::Bitmap;
+use nutrie::{TrieMode,TrieOptions,PrefixPostings};
+use index::{Inverter,Manifest,Segment,SegmentInfo,MergePolicy,LogMergePolicy};
+use index::{write_segment,merge_segments,read_deletes,write_deletes,deletes_file_name,read_keys,write_keys};
+use index::{write_run,write_segment_runs};
+
+/// Directory in the index directory for runs spilled by the writer
+static RUNS_DIR: &str = "runs";
+
+/// Adds and deletes documents of an index. Every commit writes the documents added since the
+/// previous commit as a new immutable segment, stores deletions next to the segments they
+/// belong to and publishes all of it in the segments manifest at once. After a commit
+/// segments are merged as the merge policy suggests.
+///
+/// With a memory budget set, uncommitted documents are spilled to disk as sorted runs whenever
+/// their postings outgrow the budget. On commit the runs are merged into the new segment.
+///
+/// Batches of documents added by `add_documents` are inverted by several threads. Output does
+/// not depend on the number of threads.
+pub struct IndexWriter {
+    directory: PathBuf,
+    manifest: Manifest,
+    inverter: Inverter,
+    /// All deleted DocIds of committed segments with uncommitted deletions, by segment name
+    deletes: HashMap<String, Bitmap>,
+    /// Deleted DocIds of uncommitted documents
+    pending_deletes: Bitmap,
+    /// External keys of committed segments' documents by segment name, loaded on demand
+    keys: HashMap<String, HashMap<String, DocId>>,
+    /// External keys of uncommitted documents
+    pending_keys: HashMap<String, DocId>,
+    memory_budget: Option<usize>,
+    /// Spilled runs of uncommitted documents in DocId order
+    runs: Vec<PathBuf>,
+    threads: usize,
+    merge_policy: Box<dyn MergePolicy>,
+    trie: TrieOptions,
+}
+
+impl IndexWriter {
+    /// Open the index in the directory, creating an empty one if it does not exist
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<IndexWriter> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+        let manifest = Manifest::read(&directory)?;
+        // Runs of uncommitted documents of a writer which did not finish
+        let runs_dir = directory.join(RUNS_DIR);
+        if runs_dir.exists() {
+            fs::remove_dir_all(&runs_dir)?;
+        }
+        Ok(IndexWriter {
+            directory,
+            manifest,
+            inverter: Inverter::new(),
+            deletes: HashMap::new(),
+            pending_deletes: Bitmap::new(),
+            keys: HashMap::new(),
+            pending_keys: HashMap::new(),
+            memory_budget: None,
+            runs: Vec::new(),
+            threads: 1,
+            merge_policy: Box::new(LogMergePolicy::default()),
+            trie: TrieOptions::default(),
+        })
+    }
+
+    pub fn set_merge_policy<P: MergePolicy + 'static>(&mut self, policy: P) {
+        self.merge_policy = Box::new(policy);
+    }
+
+    /// Limit memory taken by uncommitted documents to about `budget` bytes. None means no limit
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+    }
+
+    /// Number of threads inverting documents added by `add_documents`
+    pub fn set_threads(&mut self, threads: usize) {
+        assert!(threads > 0);
+        self.threads = threads;
+    }
+
+    /// Shape of tries of new and merged segments. Segments of different modes can be mixed
+    pub fn set_trie_mode(&mut self, mode: TrieMode) {
+        self.trie.mode = mode;
+    }
+
+    /// Write tries of new and merged segments in `TrieFormat::Compact`. Otherwise their format
+    /// is chosen by their terms
+    pub fn set_compact_trie(&mut self, compact: bool) {
+        self.trie.compact = compact;
+    }
+
+    /// Write an `FstDictionary` of the terms of new and merged segments next to their tries
+    pub fn set_fst_dictionary(&mut self, fst: bool) {
+        self.trie.fst = fst;
+    }
+
+    /// Inner trie nodes of new and merged segments whose merged postings are written
+    pub fn set_prefix_postings(&mut self, prefix_postings: PrefixPostings) {
+        self.trie.prefix_postings = prefix_postings;
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Number of documents added, but not yet committed
+    pub fn num_pending(&self) -> DocId {
+        self.inverter.num_docs()
+    }
+
+    /// Add a document made of terms at their positions. Returns the global DocId the document
+    /// will have once committed or None when the document has no terms and was skipped
+    pub fn add_document<S: AsRef<str>>(&mut self, fields: &[S]) -> io::Result<Option<DocId>> {
+        let local_doc = match self.inverter.add_document(fields) {
+            Some(doc_id) => doc_id,
+            None => return Ok(None),
+        };
+        if self.memory_budget.is_some_and(|budget| self.inverter.memory_usage() >= budget) {
+            self.spill()?;
+        }
+        Ok(Some(self.manifest.num_docs() + local_doc))
+    }
+
+    /// Add a batch of documents like `add_document`. Contiguous chunks of the batch are inverted
+    /// by separate threads and appended in order
+    pub fn add_documents<D, S>(&mut self, docs: &[D]) -> io::Result<Vec<Option<DocId>>>
+        where D: AsRef<[S]> + Sync, S: AsRef<str>
+    {
+        if self.threads == 1 || docs.len() < 2 {
+            return docs.iter().map(|doc| self.add_document(doc.as_ref())).collect();
+        }
+
+        let chunk_size = docs.len().div_ceil(self.threads);
+        let parts = thread::scope(|scope| {
+            let workers = docs.chunks(chunk_size).map(|chunk| scope.spawn(move || {
+                let mut inverter = Inverter::new();
+                let doc_ids = chunk.iter().map(|doc| inverter.add_document(doc.as_ref())).collect::<Vec<_>>();
+                (inverter, doc_ids)
+            })).collect::<Vec<_>>();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect::<Vec<_>>()
+        });
+
+        let mut result = Vec::with_capacity(docs.len());
+        for (inverter, doc_ids) in parts {
+            let base = self.manifest.num_docs() + self.inverter.num_docs();
+            self.inverter.append(inverter);
+            result.extend(doc_ids.into_iter().map(|doc_id| doc_id.map(|doc_id| base + doc_id)));
+            if self.memory_budget.is_some_and(|budget| self.inverter.memory_usage() >= budget) {
+                self.spill()?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Write postings of uncommitted documents held in memory as a new run
+    fn spill(&mut self) -> io::Result<()> {
+        let runs_dir = self.directory.join(RUNS_DIR);
+        fs::create_dir_all(&runs_dir)?;
+        let path = runs_dir.join(format!("run_{:06}", self.runs.len() + 1));
+        debug!("Spilling {} bytes of postings into {}", self.inverter.memory_usage(), path.display());
+        write_run(&path, self.inverter.split_off())?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Replace documents having the external key by a new document. Old versions get deleted
+    /// and the new one added with the next commit, both at once. Returns the new document's
+    /// global DocId like `add_document`. A document without terms only deletes old versions
+    pub fn update_document<S: AsRef<str>>(&mut self, key: &str, fields: &[S]) -> io::Result<Option<DocId>> {
+        if key.contains(['\n', '\r']) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("line break in document key {:?}", key)));
+        }
+        self.delete_key(key)?;
+        let doc_id = self.add_document(fields)?;
+        if let Some(doc_id) = doc_id {
+            self.pending_keys.insert(key.to_string(), doc_id - self.manifest.num_docs());
+        }
+        Ok(doc_id)
+    }
+
+    /// Delete all documents with the external key
+    fn delete_key(&mut self, key: &str) -> io::Result<()> {
+        let committed = self.manifest.num_docs();
+        let mut to_delete = Vec::new();
+        if let Some(local_doc) = self.pending_keys.remove(key) {
+            to_delete.push(committed + local_doc);
+        }
+        for (info, base) in self.manifest.segments.iter().zip(self.manifest.doc_offsets()) {
+            let keys = match self.keys.entry(info.name.clone()) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    let keys = read_keys(&self.directory.join(&info.name))?;
+                    e.insert(keys.into_iter().map(|(doc_id, key)| (key, doc_id)).collect())
+                }
+            };
+            if let Some(&local_doc) = keys.get(key) {
+                to_delete.push(base + local_doc);
+            }
+        }
+        for doc_id in to_delete {
+            self.delete_document(doc_id)?;
+        }
+        Ok(())
+    }
+
+    /// Number of deletions not yet committed
+    pub fn num_pending_deletes(&self) -> DocId {
+        let committed = self.manifest.segments.iter().filter_map(|info| {
+            self.deletes.get(&info.name).map(|deleted| deleted.len() as DocId - info.num_deleted)
+        }).sum::<DocId>();
+        committed + self.pending_deletes.len() as DocId
+    }
+
+    /// Delete the document by its global DocId, committed or not. The deletion takes effect
+    /// with the next commit. Returns false when there is no such document or it is already
+    /// deleted
+    pub fn delete_document(&mut self, doc_id: DocId) -> io::Result<bool> {
+        let committed = self.manifest.num_docs();
+        if doc_id == 0 || doc_id > committed + self.inverter.num_docs() {
+            return Ok(false);
+        }
+        if doc_id > committed {
+            return Ok(self.pending_deletes.insert(doc_id - committed));
+        }
+
+        // Last segment starting before the DocId
+        let offsets = self.manifest.doc_offsets();
+        let i = offsets.partition_point(|&base| base < doc_id) - 1;
+        let info = &self.manifest.segments[i];
+        let deleted = match self.deletes.entry(info.name.clone()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(read_deletes(&self.directory.join(&info.name), info.del_gen)?),
+        };
+        Ok(deleted.insert(doc_id - offsets[i]))
+    }
+
+    /// Write pending documents as a new segment and store pending deletions. Segments left
+    /// without live documents are dropped, which shifts global DocIds of the segments behind
+    /// them. Returns the new segment or None when no documents were added
+    pub fn commit(&mut self) -> io::Result<Option<SegmentInfo>> {
+        if self.inverter.num_docs() == 0 && self.deletes.is_empty() {
+            return Ok(None);
+        }
+
+        let mut manifest = self.manifest.clone();
+        manifest.generation += 1;
+        let generation = manifest.generation;
+        // Files no longer referenced once the new manifest is written
+        let mut obsolete = Vec::new();
+
+        for info in &mut manifest.segments {
+            let deleted = match self.deletes.get(&info.name) {
+                Some(deleted) => deleted,
+                None => continue,
+            };
+            let segment_dir = self.directory.join(&info.name);
+            write_deletes(&segment_dir, generation, deleted)?;
+            if info.del_gen > 0 {
+                obsolete.push(segment_dir.join(deletes_file_name(info.del_gen)));
+            }
+            info.del_gen = generation;
+            info.num_deleted = deleted.len() as DocId;
+            debug!("Segment {} has {} of {} docs deleted", info.name, info.num_deleted, info.num_docs);
+        }
+
+        let (live, dead): (Vec<_>, Vec<_>) = manifest.segments.into_iter().partition(|info| info.num_live_docs() > 0);
+        manifest.segments = live;
+        for info in dead {
+            info!("Dropping segment {} with all docs deleted", info.name);
+            self.keys.remove(&info.name);
+            obsolete.push(self.directory.join(&info.name));
+        }
+
+        if !self.runs.is_empty() && self.inverter.memory_usage() > 0 {
+            self.spill()?;
+        }
+        let inverter = mem::take(&mut self.inverter);
+        let runs = mem::take(&mut self.runs);
+        let pending_deletes = mem::take(&mut self.pending_deletes);
+        let mut pending_keys = mem::take(&mut self.pending_keys).into_iter().map(|(key, doc_id)| (doc_id, key)).collect::<Vec<_>>();
+        pending_keys.sort();
+        let mut new_segment = None;
+        if inverter.num_docs() > pending_deletes.len() as DocId {
+            let mut info = SegmentInfo::new(self.manifest.next_segment_name(), inverter.num_docs());
+            let segment_dir = self.directory.join(&info.name);
+            info!("Writing segment {} with {} docs", info.name, info.num_docs);
+            if runs.is_empty() {
+                write_segment(&segment_dir, inverter, self.trie)?;
+            } else {
+                write_segment_runs(&segment_dir, &runs, self.trie)?;
+            }
+            write_keys(&segment_dir, &pending_keys)?;
+            if !pending_deletes.is_empty() {
+                write_deletes(&segment_dir, generation, &pending_deletes)?;
+                info.del_gen = generation;
+                info.num_deleted = pending_deletes.len() as DocId;
+            }
+            manifest.segments.push(info.clone());
+            new_segment = Some(info);
+        }
+
+        manifest.write(&self.directory)?;
+        self.manifest = manifest;
+        self.deletes.clear();
+        if !runs.is_empty() {
+            obsolete.push(self.directory.join(RUNS_DIR));
+        }
+        remove_obsolete(obsolete);
+
+        self.maybe_merge()?;
+        Ok(new_segment)
+    }
+
+    /// Merge adjacent segments in the range into a single new segment. Deleted documents are
+    /// dropped, uncommitted deletions in the segments included, which makes them durable.
+    /// Returns None when all documents of the segments were deleted
+    pub fn merge(&mut self, range: Range<usize>) -> io::Result<Option<SegmentInfo>> {
+        assert!(range.start < range.end && range.end <= self.manifest.segments.len());
+        let segments = self.manifest.segments[range.clone()].iter().map(|info| {
+            let segment = Segment::open(&self.directory.join(&info.name), info.clone(), 0)?;
+            Ok(match self.deletes.get(&info.name) {
+                Some(deleted) => segment.with_deleted(deleted.clone()),
+                None => segment,
+            })
+        }).collect::<io::Result<Vec<_>>>()?;
+
+        let name = self.manifest.next_segment_name();
+        let num_docs = merge_segments(&segments, &self.directory.join(&name), self.trie)?;
+        let info = if num_docs > 0 { Some(SegmentInfo::new(name, num_docs)) } else { None };
+
+        let mut manifest = self.manifest.clone();
+        manifest.generation += 1;
+        let merged = manifest.segments.splice(range, info.clone()).collect::<Vec<_>>();
+        manifest.write(&self.directory)?;
+        self.manifest = manifest;
+
+        // Merged segments are not referenced by the manifest anymore
+        for segment in &merged {
+            self.keys.remove(&segment.name);
+            self.deletes.remove(&segment.name);
+        }
+        remove_obsolete(merged.into_iter().map(|segment| self.directory.join(segment.name)).collect());
+        Ok(info)
+    }
+
+    /// Run merges suggested by the merge policy until it has no more suggestions
+    pub fn maybe_merge(&mut self) -> io::Result<()> {
+        loop {
+            let merges = self.merge_policy.find_merges(&self.manifest.segments);
+            if merges.is_empty() {
+                return Ok(());
+            }
+            // Merge from the back so that ranges in front stay valid
+            for range in merges.into_iter().rev() {
+                self.merge(range)?;
+            }
+        }
+    }
+
+    /// Merge all segments into one without deleted documents
+    pub fn force_merge(&mut self) -> io::Result<()> {
+        let num_segments = self.manifest.segments.len();
+        if num_segments > 1 || self.manifest.num_deleted() > 0 || !self.deletes.is_empty() {
+            self.merge(0..num_segments)?;
+        }
+        Ok(())
+    }
+}
+
+fn remove_obsolete(paths: Vec<PathBuf>) {
+    for path in paths {
+        let removed = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        if let Err(e) = removed {
+            warn!("Could not remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use index::NoMergePolicy;
+    use test_support::TempDir;
+    use search::Searcher;
+    use nutrie::TrieFormat;
+
+    #[test]
+    fn test_delete_documents() {
+        let dir = TempDir::new("deletes");
+
+        let mut writer = dir.writer();
+        writer.set_merge_policy(NoMergePolicy);
+        writer.add_document(&["apple", "banana"]).unwrap();
+        writer.add_document(&["apple", "cherry"]).unwrap();
+        writer.add_document(&["kiwi"]).unwrap();
+        writer.commit().unwrap();
+        writer.add_document(&["apple"]).unwrap();
+        writer.add_document(&["banana"]).unwrap();
+
+        assert!(writer.delete_document(2).unwrap());
+        assert!(!writer.delete_document(2).unwrap());
+        assert!(writer.delete_document(5).unwrap());
+        assert!(!writer.delete_document(6).unwrap());
+        assert!(!writer.delete_document(0).unwrap());
+        assert_eq!(writer.num_pending_deletes(), 2);
+        writer.commit().unwrap();
+        assert_eq!(writer.num_pending_deletes(), 0);
+        assert_eq!(writer.manifest().num_deleted(), 2);
+
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["apple"], true, None), vec![1, 4]);
+        assert_eq!(searcher.search(&["banana"], true, None), vec![1]);
+        assert_eq!(searcher.search(&["cherry"], true, None), Vec::<DocId>::new());
+
+        // Previous generation of deletions is replaced
+        assert!(writer.delete_document(3).unwrap());
+        writer.commit().unwrap();
+        let first = &writer.manifest().segments[0];
+        let deletes = fs::read_dir(dir.join(&first.name)).unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with("deletes_"))
+            .count();
+        assert_eq!(deletes, 1);
+        assert_eq!(first.num_deleted, 2);
+
+        writer.force_merge().unwrap();
+        assert_eq!(writer.manifest().segments.len(), 1);
+        assert_eq!(writer.manifest().num_docs(), 2);
+        assert_eq!(writer.manifest().num_deleted(), 0);
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["apple"], true, None), vec![1, 2]);
+        assert_eq!(searcher.search(&["kiwi"], true, None), Vec::<DocId>::new());
+        assert_eq!(searcher.search(&["cher"], false, None), Vec::<DocId>::new());
+
+        // Segment without live documents is dropped
+        writer.delete_document(1).unwrap();
+        writer.delete_document(2).unwrap();
+        writer.commit().unwrap();
+        assert!(writer.manifest().segments.is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_merge_uncommitted_deletes() {
+        let dir = TempDir::new("merge-deletes");
+
+        let mut writer = dir.writer();
+        writer.set_merge_policy(NoMergePolicy);
+        writer.add_document(&["apple", "banana"]).unwrap();
+        writer.add_document(&["apple"]).unwrap();
+        writer.commit().unwrap();
+        writer.add_document(&["apple", "kiwi"]).unwrap();
+        writer.commit().unwrap();
+        writer.add_document(&["apple"]).unwrap();
+
+        // Deletions of merged segments are applied without a commit
+        assert!(writer.delete_document(1).unwrap());
+        assert!(writer.delete_document(4).unwrap());
+        writer.force_merge().unwrap();
+        assert_eq!(writer.manifest().segments.len(), 1);
+        assert_eq!(writer.manifest().num_docs(), 2);
+        assert_eq!(writer.num_pending_deletes(), 1);
+        assert_eq!(Searcher::open(&dir).unwrap().search(&["apple"], true, None), vec![1, 2]);
+        writer.commit().unwrap();
+        assert_eq!(Searcher::open(&dir).unwrap().search(&["apple"], true, None), vec![1, 2]);
+
+        // A single segment is merged to drop them too
+        assert!(writer.delete_document(2).unwrap());
+        writer.force_merge().unwrap();
+        assert_eq!(writer.manifest().num_deleted(), 0);
+        assert_eq!(Searcher::open(&dir).unwrap().search(&["apple"], true, None), vec![1]);
+    }
+
+    #[test]
+    fn test_threads_do_not_change_output() {
+        let docs = (0..200).map(|i| {
+            (0..i % 7 + 1).map(|j| format!("t{}", (i * 31 + j * 17) % 53)).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+
+        let mut outputs = Vec::new();
+        for &threads in &[1, 3, 8] {
+            let dir = TempDir::new(&format!("threads-{}", threads));
+            let mut writer = dir.writer();
+            writer.set_threads(threads);
+            let doc_ids = writer.add_documents(&docs[..150]).unwrap();
+            assert_eq!(doc_ids, (1..151).map(Some).collect::<Vec<_>>());
+            writer.add_documents(&docs[150..]).unwrap();
+            writer.commit().unwrap();
+
+            let name = &writer.manifest().segments[0].name;
+            let files = ["dict", "docs", "tfs", "positions", "meta"].iter().map(|file| {
+                fs::read(dir.join(name).join(file)).unwrap()
+            }).collect::<Vec<_>>();
+            outputs.push(files);
+        }
+        assert!(outputs.iter().all(|files| *files == outputs[0]));
+    }
+
+    #[test]
+    fn test_update_documents() {
+        let dir = TempDir::new("updates");
+
+        let mut writer = dir.writer();
+        writer.set_merge_policy(NoMergePolicy);
+        assert_eq!(writer.update_document("a", &["apple"]).unwrap(), Some(1));
+        assert_eq!(writer.update_document("b", &["banana"]).unwrap(), Some(2));
+        // Replaced before it was committed
+        assert_eq!(writer.update_document("a", &["apricot"]).unwrap(), Some(3));
+        writer.commit().unwrap();
+        assert!(writer.update_document("bad\nkey", &["kiwi"]).is_err());
+
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["ap"], false, None), vec![3]);
+
+        // Old version and the new one become visible in one commit
+        assert_eq!(writer.update_document("b", &["blueberry"]).unwrap(), Some(4));
+        assert_eq!(writer.update_document("c", &["cherry"]).unwrap(), Some(5));
+        assert_eq!(Searcher::open(&dir).unwrap().search(&["b"], false, None), vec![2]);
+        writer.commit().unwrap();
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["b"], false, None), vec![4]);
+
+        // Keys survive merging
+        writer.force_merge().unwrap();
+        assert_eq!(writer.manifest().num_docs(), 3);
+        assert_eq!(writer.update_document("a", &["avocado"]).unwrap(), Some(4));
+        assert_eq!(writer.update_document("c", &[""]).unwrap(), None);
+        writer.commit().unwrap();
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["a"], false, None), vec![4]);
+        assert_eq!(searcher.search(&["blueberry"], true, None), vec![2]);
+        assert_eq!(searcher.search(&["cherry"], true, None), Vec::<DocId>::new());
+        assert_eq!(writer.manifest().num_deleted(), 2);
+    }
+
+    #[test]
+    fn test_trie_modes() {
+        let docs = [
+            ["été", "ete"], ["étage", "中文"], ["e\u{301}t\u{e9}", "中"],
+            ["😀", "a\0b"], ["😀😁", "ß"], ["😁", "e"],
+        ];
+        let queries: &[&[&str]] = &[&["é"], &["ét"], &["e"], &["e\u{301}"], &["中"], &["中文"], &["😀"], &["😁"], &["ß"], &["a"], &["a\0b"]];
+
+        let mut results = Vec::new();
+        for &mode in &[TrieMode::Chars, TrieMode::Bytes] {
+            let dir = TempDir::new(&format!("trie-modes-{:?}", mode));
+            let mut writer = dir.writer();
+            writer.set_merge_policy(NoMergePolicy);
+            writer.set_trie_mode(mode);
+            writer.add_documents(&docs[..3]).unwrap();
+            writer.commit().unwrap();
+            // Segments of both modes in one index, and merged together
+            writer.set_trie_mode(if mode == TrieMode::Chars { TrieMode::Bytes } else { TrieMode::Chars });
+            writer.add_documents(&docs[3..]).unwrap();
+            writer.commit().unwrap();
+            for merged in &[false, true] {
+                if *merged {
+                    writer.force_merge().unwrap();
+                }
+                let searcher = Searcher::open(&dir).unwrap();
+                results.push(queries.iter().map(|q| {
+                    (searcher.search(q, false, None), searcher.search(q, true, None))
+                }).collect::<Vec<_>>());
+            }
+        }
+
+        assert_eq!(results[0][0], (vec![1, 2], vec![]));
+        assert_eq!(results[0][2], (vec![1, 3, 6], vec![6]));
+        assert_eq!(results[0][3], (vec![3], vec![]));
+        assert_eq!(results[0][6], (vec![4, 5], vec![4]));
+        assert_eq!(results[0][10], (vec![], vec![]));
+        assert!(results.iter().all(|r| *r == results[0]));
+    }
+
+    #[test]
+    fn test_long_terms() {
+        let dir = TempDir::new("long-terms");
+        let long = "u".repeat(70_000);
+
+        let mut writer = dir.writer();
+        writer.set_merge_policy(NoMergePolicy);
+        writer.add_document(&["short", "url"]).unwrap();
+        writer.commit().unwrap();
+        // Spilled runs carry the long term over to the segment
+        writer.set_memory_budget(Some(1));
+        writer.add_document(&[format!("{}1", long), "short".to_string()]).unwrap();
+        writer.add_document(&[format!("{}2", long)]).unwrap();
+        writer.commit().unwrap();
+
+        let formats = |writer: &IndexWriter| {
+            let searcher = Searcher::open(&dir).unwrap();
+            assert_eq!(searcher.search(&[&long], false, None), vec![2, 3]);
+            assert_eq!(searcher.search(&[format!("{}2", long)], true, None), vec![3]);
+            assert_eq!(searcher.search(&["u"], false, None), vec![1, 2, 3]);
+            assert_eq!(searcher.search(&["short"], true, None), vec![1, 2]);
+            assert_eq!(searcher.segments().len(), writer.manifest().segments.len());
+            searcher.segments().iter().map(|s| s.trie_format()).collect::<Vec<_>>()
+        };
+        assert_eq!(formats(&writer), vec![TrieFormat::Narrow, TrieFormat::Wide]);
+        writer.force_merge().unwrap();
+        assert_eq!(formats(&writer), vec![TrieFormat::Wide]);
+        // Compact format has no limits
+        writer.set_compact_trie(true);
+        writer.add_document(&["url"]).unwrap();
+        writer.commit().unwrap();
+        writer.force_merge().unwrap();
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.segments()[0].trie_format(), TrieFormat::Compact);
+        assert_eq!(searcher.search(&[&long], false, None), vec![2, 3]);
+        assert_eq!(searcher.search(&["u"], false, None), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_fst_dictionary() {
+        let dir = TempDir::new("fst-dictionary");
+
+        let mut writer = dir.writer();
+        writer.set_merge_policy(NoMergePolicy);
+        writer.add_document(&["band", "banana"]).unwrap();
+        writer.commit().unwrap();
+        writer.set_fst_dictionary(true);
+        writer.add_document(&["bandana", "band", "apple"]).unwrap();
+        writer.commit().unwrap();
+
+        let check = |num_segments: usize| {
+            let searcher = Searcher::open(&dir).unwrap();
+            assert_eq!(searcher.segments().len(), num_segments);
+            let segment = searcher.segments().last().unwrap();
+            let dict = segment.dict();
+            let fst = segment.fst_dict().unwrap();
+            let leaves = dict.leaves().map(|(term, node)| (term, (node.postings_ptr, node.num_postings, node.term_id))).collect::<Vec<_>>();
+            let terms = fst.iter().map(|(term, info)| (term, (info.postings_ptr, info.num_postings, info.term_id))).collect::<Vec<_>>();
+            assert_eq!(terms, leaves);
+            assert_eq!(fst.prefix("band").map(|(term, _)| term).collect::<Vec<_>>(), vec!["band", "bandana"]);
+        };
+        check(2);
+        assert!(Searcher::open(&dir).unwrap().segments()[0].fst_dict().is_none());
+        writer.force_merge().unwrap();
+        check(1);
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.segments()[0].fst_dict().unwrap().find_exact("band").unwrap().num_postings, 2);
+    }
+}
@@ -3,12 +3,34 @@ pub use self::types::*;
 pub use self::termbuf::*;
 pub use self::postings::*;
 pub use self::nutrie::*;
+pub use self::fst::*;
+pub use self::dictionary::*;
 pub use self::meta::*;
+pub use self::explain::*;
+pub use self::index::*;
+pub use self::search::*;
+pub use self::bitmap::*;
+pub use self::cache::*;
+pub use self::corpus::*;
 
 #[macro_use]
 pub mod util;
+#[macro_use]
+pub mod log;
 pub mod nutrie;
+pub mod fst;
+pub mod dictionary;
 pub mod postings;
 pub mod termbuf;
 pub mod types;
 pub mod meta;
+pub mod explain;
+pub mod index;
+pub mod search;
+pub mod bitmap;
+pub mod cache;
+#[doc(hidden)]
+pub mod random;
+pub mod corpus;
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,139 @@
+use std::{env,fmt};
+use std::io::{self,Write};
+use std::sync::atomic::{AtomicUsize,Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn from_usize(n: usize) -> Option<Level> {
+        match n {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Level> {
+        match s.to_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        f.pad(name)
+    }
+}
+
+pub static LOG_ENV: &str = "SIRKA_LOG";
+
+const UNINITIALIZED: usize = usize::MAX;
+const OFF: usize = 0;
+
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+
+/// Set maximum level of messages to be logged. `None` turns logging off
+pub fn set_max_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(OFF, |l| l as usize), Ordering::Relaxed);
+}
+
+/// Maximum logged level. Unless set explicitly it is read from SIRKA_LOG environment variable.
+/// Logging is off by default
+pub fn max_level() -> Option<Level> {
+    let mut max = MAX_LEVEL.load(Ordering::Relaxed);
+    if max == UNINITIALIZED {
+        let level = env::var(LOG_ENV).ok().and_then(|s| Level::parse(&s));
+        max = level.map_or(OFF, |l| l as usize);
+        MAX_LEVEL.store(max, Ordering::Relaxed);
+    }
+    Level::from_usize(max)
+}
+
+pub fn enabled(level: Level) -> bool {
+    match max_level() {
+        Some(max) => level <= max,
+        None => false,
+    }
+}
+
+pub fn write(level: Level, module: &str, args: fmt::Arguments) {
+    let stderr = io::stderr();
+    let mut handle = stderr.lock();
+    let _ = writeln!(handle, "[{:<5} {}] {}", level, module, args);
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, module_path!(), format_args!($($arg)+));
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Error, $($arg)+))
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Warn, $($arg)+))
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Info, $($arg)+))
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Debug, $($arg)+))
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Trace, $($arg)+))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(Level::parse("DEBUG"), Some(Level::Debug));
+        assert_eq!(Level::parse("nope"), None);
+        assert!(Level::Error < Level::Trace);
+
+        set_max_level(Some(Level::Info));
+        assert!(enabled(Level::Warn));
+        assert!(enabled(Level::Info));
+        assert!(!enabled(Level::Debug));
+        set_max_level(None);
+        assert!(!enabled(Level::Error));
+    }
+}
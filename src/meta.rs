@@ -1,7 +1,8 @@
 use std::mem;
 use std::slice;
 
-#[derive(Debug)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct IndexMeta {
     pub dict_size: u64,
     pub root_ptr: u64,
@@ -9,15 +10,49 @@ pub struct IndexMeta {
     pub docs_size: u64,
     pub tfs_size: u64,
     pub positions_size: u64,
+    /// Options the segment was written with, see the flag constants. Added after the other
+    /// fields, missing in older segments
+    pub flags: u64,
 }
 
 impl IndexMeta {
-    pub fn from_bytes(bs: &[u8]) -> &Self {
-        assert!(bs.len() >= mem::size_of::<IndexMeta>());
-        unsafe { mem::transmute(&bs[0]) }
+    /// Trie of the segment branches on bytes, see `TrieMode::Bytes`
+    pub const BYTE_TRIE: u64 = 1;
+    /// Trie of the segment is in `TrieFormat::Wide`
+    pub const WIDE_TRIE: u64 = 2;
+    /// Trie of the segment is in `TrieFormat::Compact`
+    pub const COMPACT_TRIE: u64 = 4;
+    /// Segment has an `FstDictionary` of its terms next to the trie
+    pub const FST_DICTIONARY: u64 = 8;
+
+    /// Parse meta of any version. Fields missing in older versions are zero
+    pub fn from_bytes(bs: &[u8]) -> Self {
+        let mut meta = IndexMeta::default();
+        let len = bs.len().min(mem::size_of::<IndexMeta>());
+        assert!(len >= mem::size_of::<u64>() * 6);
+        unsafe {
+            let dst = slice::from_raw_parts_mut(&mut meta as *mut _ as *mut u8, len);
+            dst.copy_from_slice(&bs[..len]);
+        }
+        meta
     }
 
     pub fn to_bytes(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<IndexMeta>()) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_older_meta() {
+        let meta = IndexMeta { dict_size: 1, root_ptr: 2, term_buffer_size: 3, flags: IndexMeta::BYTE_TRIE, ..Default::default() };
+        let parsed = IndexMeta::from_bytes(meta.to_bytes());
+        assert_eq!((parsed.dict_size, parsed.term_buffer_size, parsed.flags), (1, 3, IndexMeta::BYTE_TRIE));
+        // Written before flags existed
+        let parsed = IndexMeta::from_bytes(&meta.to_bytes()[..48]);
+        assert_eq!((parsed.root_ptr, parsed.flags), (2, 0));
+    }
+}
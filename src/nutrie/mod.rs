@@ -3,6 +3,7 @@ pub mod write;
 
 pub use self::write::*;
 pub use self::read::*;
+use std::{mem,str};
 use types::{DocId,TermId};
 
 // TODO packed necessary?
@@ -11,9 +12,206 @@ use types::{DocId,TermId};
 pub struct TrieNodeHeader {
     pub num_postings: u64,
     pub postings_ptr: DocId,
+    /// Saturated in `TrieFormat::Wide`, the exact value is in the following `WideLabel`
     pub term_ptr: u32,
     pub term_id: TermId, // TERMID
     pub num_children: u32,
+    /// Saturated in `TrieFormat::Wide`, the exact value is in the following `WideLabel`
     pub term_length: u16,
 }
 
+/// Place of a node's label in the term buffer. Follows every header in `TrieFormat::Wide`
+#[repr(C)]
+#[derive(Debug)]
+pub struct WideLabel {
+    pub term_ptr: u64,
+    pub term_length: u64,
+}
+
+/// Layout of trie nodes. A node is its header, followed by the first letters of its
+/// children and by pointers to them, both ascending
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrieFormat {
+    /// Labels of at most `u16::MAX - 1` bytes, term buffer and dictionary under 4GiB. Child
+    /// pointers are u32
+    #[default]
+    Narrow,
+    /// Headers are followed by a `WideLabel` and child pointers are u64, aligned by padding
+    /// after the first letters
+    Wide,
+    /// Unaligned nodes without headers, for smaller dictionaries. A node is a flags byte (see
+    /// `COMPACT_LEAF`), term_ptr and term_length in as many little endian bytes as the flags
+    /// tell and, unless a leaf, a varint num_children followed by the children: a byte with
+    /// the width of their offsets, the little endian offsets and the first letters, varints
+    /// for up to `COMPACT_INLINE_FANOUT` children and little endian u32 otherwise. Offsets are
+    /// relative to the node, children always precede their parent. Varints term_id,
+    /// num_postings and postings_ptr come last, lookups skip them on the way down. No limits
+    /// on sizes
+    Compact,
+}
+
+/// How tries of new segments are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrieOptions {
+    pub mode: TrieMode,
+    /// Write `TrieFormat::Compact` instead of choosing between narrow and wide
+    pub compact: bool,
+    /// Also write an `FstDictionary` of the terms, see `Segment::fst_dict`
+    pub fst: bool,
+    pub prefix_postings: PrefixPostings,
+}
+
+/// Inner trie nodes whose merged postings, the union of the postings of all terms under them,
+/// are written. Prefix searches ending at a node without them merge the postings of its
+/// nearest descendants having them at query time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PrefixPostings {
+    /// Every inner node, the root included
+    All,
+    /// Every inner node but the root, whose postings would be those of the whole segment
+    #[default]
+    AllButRoot,
+    /// Inner nodes but the root with at least this many documents
+    AtLeast(u64),
+    /// Inner nodes but the root a prefix search would otherwise merge from more than
+    /// `max_lists` postings of their descendants, unless they have fewer than `min_docs`
+    /// documents and are cheap to merge anyway. Bounds the merges of prefix searches without
+    /// storing small unions
+    Bounded { max_lists: u64, min_docs: u64 },
+    /// No inner node, only terms have postings
+    None,
+}
+
+impl PrefixPostings {
+    /// `Bounded` by default limits
+    pub const AUTO: PrefixPostings = PrefixPostings::Bounded { max_lists: 8, min_docs: 256 };
+
+    /// Whether any node of the kind may get its merged postings written. Postings under the
+    /// others are never merged at index time
+    pub fn may_merge(self, is_root: bool) -> bool {
+        match self {
+            PrefixPostings::All => true,
+            PrefixPostings::AllButRoot | PrefixPostings::AtLeast(_) | PrefixPostings::Bounded { .. } => !is_root,
+            PrefixPostings::None => false,
+        }
+    }
+
+    /// Whether a node may get its merged postings written, decided before merging them from
+    /// at most `max_postings` postings of its descendants in `num_lists` lists
+    pub fn may_store(self, is_root: bool, max_postings: u64, num_lists: u64) -> bool {
+        self.may_merge(is_root) && self.stores(max_postings, num_lists)
+    }
+
+    /// Whether merged postings with `num_postings` documents are written, given `num_lists`
+    /// postings of descendants merged at query time without them
+    pub fn stores(self, num_postings: u64, num_lists: u64) -> bool {
+        match self {
+            PrefixPostings::AtLeast(min) => num_postings >= min,
+            PrefixPostings::Bounded { max_lists, min_docs } => num_lists > max_lists && num_postings >= min_docs,
+            _ => true,
+        }
+    }
+}
+
+impl TrieOptions {
+    pub fn format(&self, stats: &TermStats) -> TrieFormat {
+        if self.compact { TrieFormat::Compact } else { TrieFormat::for_terms(stats) }
+    }
+}
+
+/// Flag of leaf nodes in `TrieFormat::Compact`, they have no num_children. Bits 1-3 and 4-6
+/// of the flags hold the byte widths of term_ptr and term_length minus one
+pub const COMPACT_LEAF: u8 = 1;
+/// Children of nodes with up to this many are scanned, not binary searched
+pub const COMPACT_INLINE_FANOUT: usize = 8;
+
+impl TrieFormat {
+    /// Narrow format unless the terms could overflow it
+    pub fn for_terms(stats: &TermStats) -> TrieFormat {
+        // Forks add at most one inner node per term. Every node takes a header and its
+        // first letter and pointer in the parent
+        let max_nodes = 2 * stats.num_terms + 2;
+        let max_dict_size = max_nodes * (mem::size_of::<TrieNodeHeader>() as u64 + 8);
+        // Labels of leaves count the terminating '\0'
+        if stats.max_term_len + 1 >= u16::MAX as u64 || stats.term_bytes > u32::MAX as u64 || max_dict_size > u32::MAX as u64 {
+            TrieFormat::Wide
+        } else {
+            TrieFormat::Narrow
+        }
+    }
+}
+
+/// Sizes of the terms a trie is written for, enough to choose its format
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TermStats {
+    pub num_terms: u64,
+    /// Total length of the terms
+    pub term_bytes: u64,
+    pub max_term_len: u64,
+}
+
+impl TermStats {
+    pub fn of<I>(terms: I) -> Self
+        where I: IntoIterator,
+              I::Item: AsRef<str>
+    {
+        let mut stats = TermStats::default();
+        for term in terms {
+            let term = term.as_ref();
+            stats.num_terms += 1;
+            stats.term_bytes += term.len() as u64;
+            stats.max_term_len = stats.max_term_len.max(term.len() as u64);
+        }
+        stats
+    }
+
+    /// Bound of the terms of both, counting shared terms twice
+    pub fn add(&mut self, other: &TermStats) {
+        self.num_terms += other.num_terms;
+        self.term_bytes += other.term_bytes;
+        self.max_term_len = self.max_term_len.max(other.max_term_len);
+    }
+}
+
+/// Unit of terms the trie branches on. Terms are the same in both modes, only the shape of
+/// the trie differs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrieMode {
+    /// Children are keyed by Unicode scalar values, so every node's label is valid UTF-8
+    #[default]
+    Chars,
+    /// Children are keyed by bytes of UTF-8. Chars are never decoded and labels of inner nodes
+    /// may split multi-byte chars
+    Bytes,
+}
+
+impl TrieMode {
+    /// Length in bytes of the common prefix, never splitting a char in `Chars` mode
+    pub fn common_prefix_len(self, a: &[u8], b: &[u8]) -> usize {
+        let mut len = a.iter().zip(b).take_while(|&(x, y)| x == y).count();
+        if self == TrieMode::Chars {
+            // Back off to the start of a char. Both sides have the same char up to there
+            while len > 0 && len < a.len() && is_continuation_byte(a[len]) {
+                len -= 1;
+            }
+        }
+        len
+    }
+
+    /// Key of the child the non-empty rest of a term continues to
+    pub fn first_letter(self, s: &[u8]) -> u32 {
+        match self {
+            TrieMode::Chars => {
+                let len = s.iter().skip(1).take_while(|&&b| is_continuation_byte(b)).count() + 1;
+                // Terms are valid UTF-8 and they are never split inside a char in this mode
+                let first = unsafe { str::from_utf8_unchecked(&s[..len]) };
+                first.chars().next().unwrap() as u32
+            }
+            TrieMode::Bytes => s[0] as u32,
+        }
+    }
+}
+
+fn is_continuation_byte(b: u8) -> bool {
+    b & 0xc0 == 0x80
+}
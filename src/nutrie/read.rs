@@ -1,40 +1,85 @@
-use std::{mem,slice,str};
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::mem;
 use std::io::Read;
-use nutrie::TrieNodeHeader;
-use util::*;
+use types::{DocId,TermId};
+use util::{bytes_to_typed,read_varint};
+use nutrie::{TrieNodeHeader,WideLabel,TrieMode,TrieFormat,COMPACT_LEAF,COMPACT_INLINE_FANOUT};
 
-impl TrieNodeHeader {
-    fn from_bytes<'a>(bs: *const u8) -> &'a TrieNodeHeader {
-        unsafe { mem::transmute(bs) }
+/// Node of a `StaticTrie` decoded from its dictionary, whatever the format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaticNode {
+    pub num_postings: u64,
+    pub postings_ptr: DocId,
+    pub term_id: TermId,
+    pub num_children: u32,
+    link: Link,
+}
+
+impl StaticNode {
+    /// Terms are stored with a terminating '\0' which is not present in the term buffer. Leaf
+    /// nodes are exactly the terminated terms.
+    pub fn is_leaf(&self) -> bool {
+        self.link.is_leaf()
     }
+}
 
-    fn term<'a>(&self, term_buffer: &'a [u8]) -> &'a str {
-        unsafe {
-            let slice = slice::from_raw_parts(&term_buffer[self.term_ptr as usize] as *const u8, self.term_length as usize);
-            str::from_utf8_unchecked(slice)
-        }
+/// Part of a node needed to walk the trie. The rest is decoded only for nodes handed out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Link {
+    /// Start of the node in the dictionary
+    ptr: usize,
+    /// Label in the term buffer, counting the terminating '\0' of leaves
+    term_ptr: usize,
+    term_length: usize,
+    num_children: u32,
+    /// Start of the children's first letters in the dictionary
+    children_ptr: usize,
+}
+
+impl Link {
+    #[inline]
+    fn is_leaf(&self) -> bool {
+        self.num_children == 0 && self.term_length > 0
     }
 
-    fn get_children_index(&self) -> &[u32] {
-        unsafe {
-            let index_ptr = (self as *const Self).offset(1) as *const u32;
-            slice::from_raw_parts(index_ptr, self.num_children as usize)
-        }
+    /// Node's own part of the term, without the terminating '\0' of leaf nodes
+    #[inline]
+    fn label<'a>(&self, term_buffer: &'a [u8]) -> &'a [u8] {
+        let len = if self.is_leaf() { self.term_length - 1 } else { self.term_length };
+        &term_buffer[self.term_ptr..self.term_ptr + len]
     }
+}
 
-    fn get_child_pointers(&self) -> &[u32] {
-        unsafe {
-            let children_index  = (self as *const Self).offset(1) as *const u32;
-            let child_pointers = children_index.offset(self.num_children as isize);
-            slice::from_raw_parts(child_pointers, self.num_children as usize)
-        }
+impl TrieNodeHeader {
+    fn from_bytes(bs: &[u8]) -> &TrieNodeHeader {
+        assert!(bs.len() >= mem::size_of::<TrieNodeHeader>());
+        unsafe { &*(bs.as_ptr() as *const TrieNodeHeader) }
+    }
+}
+
+impl WideLabel {
+    fn from_bytes(bs: &[u8]) -> &WideLabel {
+        assert!(bs.len() >= mem::size_of::<WideLabel>());
+        unsafe { &*(bs.as_ptr() as *const WideLabel) }
+    }
+}
+
+#[inline]
+fn read_uint_le(bs: &[u8], at: usize, width: usize) -> usize {
+    let mask = if width == 8 { !0 } else { (1 << (8 * width)) - 1 };
+    match bs.get(at..at + 8) {
+        Some(word) => (u64::from_le_bytes(word.try_into().unwrap()) & mask) as usize,
+        None => bs[at..at + width].iter().rev().fold(0, |x, &b| x << 8 | b as usize),
     }
 }
 
 pub struct StaticTrie<'a> {
-    root: &'a TrieNodeHeader,
+    root_ptr: usize,
     trie_buffer: &'a [u8],
     term_buffer: &'a [u8],
+    mode: TrieMode,
+    format: TrieFormat,
 }
 
 impl<'a> StaticTrie<'a> {
@@ -44,44 +89,465 @@ impl<'a> StaticTrie<'a> {
         bytes
     }
 
-    pub fn new(bytes: &'a [u8], dict_size: usize, root_ptr: usize, terms_size: usize) -> Self {
+    pub fn new(bytes: &'a [u8], dict_size: usize, root_ptr: usize, _terms_size: usize) -> Self {
         let (trie, terms) = bytes.split_at(dict_size);
         StaticTrie {
-            root: TrieNodeHeader::from_bytes(&trie[root_ptr] as *const _),
+            root_ptr,
             trie_buffer: trie,
             term_buffer: terms,
+            mode: TrieMode::Chars,
+            format: TrieFormat::Narrow,
         }
     }
 
-    pub fn find_term(&self, mut term: &str, find_nearest: bool) -> Option<&TrieNodeHeader> {
-        let mut cursor = self.root;
-        loop {
-            let current_term = cursor.term(self.term_buffer);
-            println!("looking for: '{}', cursor term: '{}', len: {}", term, current_term, cursor.term_length);
-            println!("CURSOR: {:?}", cursor);
-            let skip = common_prefix_len(current_term, term);
-            if skip < term.len() {
-                term = &term[skip..];
-                let first_letter = first_letter(term);
-                let children_index = cursor.get_children_index();
-                let child_index = match children_index.binary_search(&first_letter) {
-                    Ok(index) => index,
-                    Err(_) => return None,
+    /// Read the trie as written in the given mode
+    pub fn with_mode(mut self, mode: TrieMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Read the trie as written in the given format
+    pub fn with_format(mut self, format: TrieFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn root(&self) -> StaticNode {
+        self.node_at(self.link_at(self.root_ptr))
+    }
+
+    /// Node's own part of its term. See `TrieEntry::term` for the whole term. Parts of chars
+    /// split by a byte trie are replaced by U+FFFD
+    pub fn label(&self, node: &StaticNode) -> Cow<'a, str> {
+        String::from_utf8_lossy(node.link.label(self.term_buffer))
+    }
+
+    fn link_at(&self, ptr: usize) -> Link {
+        self.link_in(self.format, ptr)
+    }
+
+    /// Inlined with a constant `format` by lookups, which dispatch on it once per term
+    #[inline(always)]
+    fn link_in(&self, format: TrieFormat, ptr: usize) -> Link {
+        let bs = &self.trie_buffer[ptr..];
+        match format {
+            TrieFormat::Narrow => {
+                let header = TrieNodeHeader::from_bytes(bs);
+                Link {
+                    ptr,
+                    term_ptr: header.term_ptr as usize,
+                    term_length: header.term_length as usize,
+                    num_children: header.num_children,
+                    children_ptr: ptr + mem::size_of::<TrieNodeHeader>(),
+                }
+            }
+            TrieFormat::Wide => {
+                let header = TrieNodeHeader::from_bytes(bs);
+                let wide = WideLabel::from_bytes(&bs[mem::size_of::<TrieNodeHeader>()..]);
+                Link {
+                    ptr,
+                    term_ptr: wide.term_ptr as usize,
+                    term_length: wide.term_length as usize,
+                    num_children: header.num_children,
+                    children_ptr: ptr + mem::size_of::<TrieNodeHeader>() + mem::size_of::<WideLabel>(),
+                }
+            }
+            TrieFormat::Compact => {
+                let ptr_width = (bs[0] >> 1 & 7) as usize + 1;
+                let length_width = (bs[0] >> 4 & 7) as usize + 1;
+                let term_ptr = read_uint_le(bs, 1, ptr_width);
+                let term_length = read_uint_le(bs, 1 + ptr_width, length_width);
+                let mut at = 1 + ptr_width + length_width;
+                let num_children = if bs[0] & COMPACT_LEAF != 0 { 0 } else { read_varint(bs, &mut at) as u32 };
+                Link { ptr, term_ptr, term_length, num_children, children_ptr: ptr + at }
+            }
+        }
+    }
+
+    #[inline]
+    fn node_at(&self, link: Link) -> StaticNode {
+        let (term_id, num_postings, postings_ptr) = match self.format {
+            TrieFormat::Narrow | TrieFormat::Wide => {
+                let header = TrieNodeHeader::from_bytes(&self.trie_buffer[link.ptr..]);
+                (header.term_id, header.num_postings, header.postings_ptr)
+            }
+            TrieFormat::Compact => {
+                // Fields follow the children
+                let n = link.num_children as usize;
+                let bs = &self.trie_buffer[link.children_ptr..];
+                let mut at = if n == 0 {
+                    0
+                } else if n <= COMPACT_INLINE_FANOUT {
+                    let mut at = 1 + bs[0] as usize * n;
+                    for _ in 0..n {
+                        read_varint(bs, &mut at);
+                    }
+                    at
+                } else {
+                    1 + bs[0] as usize * n + 4 * n
                 };
-                let child_pointer = cursor.get_child_pointers()[child_index] as usize;
-                // println!("child index: {:?}", children_index);
-                let bufslice = &self.trie_buffer[child_pointer..];
-                cursor = TrieNodeHeader::from_bytes(bufslice.as_ptr());
-            } else if skip > term.len() {
-                if find_nearest {
-                    return Some(&cursor);
+                (read_varint(bs, &mut at) as TermId, read_varint(bs, &mut at), read_varint(bs, &mut at))
+            }
+        };
+        StaticNode { num_postings, postings_ptr, term_id, num_children: link.num_children, link }
+    }
+
+    /// Pointer to the child with the first letter
+    #[inline(always)]
+    fn find_child(&self, format: TrieFormat, node: &Link, first_letter: u32) -> Option<usize> {
+        let n = node.num_children as usize;
+        let bs = &self.trie_buffer[node.children_ptr..];
+        match format {
+            TrieFormat::Narrow => {
+                let i = tryopt!(bytes_to_typed::<u32>(&bs[..4 * n]).binary_search(&first_letter).ok());
+                Some(bytes_to_typed::<u32>(&bs[4 * n..8 * n])[i] as usize)
+            }
+            TrieFormat::Wide => {
+                let i = tryopt!(bytes_to_typed::<u32>(&bs[..4 * n]).binary_search(&first_letter).ok());
+                // Wide pointers are aligned to 8 bytes
+                let start = 4 * n + 4 * (n % 2);
+                Some(bytes_to_typed::<u64>(&bs[start..start + 8 * n])[i] as usize)
+            }
+            TrieFormat::Compact => {
+                let width = bs[0] as usize;
+                let letters = &bs[1 + width * n..];
+                let i = if n <= COMPACT_INLINE_FANOUT {
+                    let mut at = 0;
+                    let mut scan = (0..n).map(|_| read_varint(letters, &mut at) as u32).enumerate();
+                    let (i, letter) = tryopt!(scan.find(|&(_, letter)| letter >= first_letter));
+                    if letter != first_letter {
+                        return None;
+                    }
+                    i
                 } else {
-                    return None;
-                }
+                    // Unaligned first letters
+                    let (letters, _) = letters[..4 * n].as_chunks::<4>();
+                    tryopt!(letters.binary_search_by(|letter| u32::from_le_bytes(*letter).cmp(&first_letter)).ok())
+                };
+                Some(node.ptr - read_uint_le(bs, 1 + width * i, width))
+            }
+        }
+    }
+
+    /// Pointers to all children in order of their first letters
+    fn child_ptrs(&self, node: &Link) -> Vec<usize> {
+        let n = node.num_children as usize;
+        let bs = &self.trie_buffer[node.children_ptr..];
+        match self.format {
+            TrieFormat::Narrow => bytes_to_typed::<u32>(&bs[4 * n..8 * n]).iter().map(|&ptr| ptr as usize).collect(),
+            TrieFormat::Wide => {
+                let start = 4 * n + 4 * (n % 2);
+                bytes_to_typed::<u64>(&bs[start..start + 8 * n]).iter().map(|&ptr| ptr as usize).collect()
+            }
+            TrieFormat::Compact => {
+                let width = bs[0] as usize;
+                (0..n).map(|i| node.ptr - read_uint_le(bs, 1 + width * i, width)).collect()
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn child(&self, format: TrieFormat, node: &Link, first_letter: u32) -> Option<Link> {
+        self.find_child(format, node, first_letter).map(|ptr| self.link_in(format, ptr))
+    }
+
+    pub fn children(&self, node: &StaticNode) -> Vec<StaticNode> {
+        self.child_ptrs(&node.link).into_iter().map(|ptr| self.node_at(self.link_at(ptr))).collect()
+    }
+
+    /// Iterate all nodes depth first. Nodes come in lexicographic order of their terms
+    pub fn iter<'t>(&'t self) -> TrieIter<'t, 'a> {
+        TrieIter {
+            trie: self,
+            stack: vec![(self.root(), 0, Vec::new())],
+        }
+    }
+
+    /// Leaves with their whole terms in lexicographic order
+    pub fn leaves<'t>(&'t self) -> impl Iterator<Item = (String, StaticNode)> + 't {
+        self.iter().filter(|e| e.node.is_leaf()).map(|e| (e.term, e.node))
+    }
+
+    /// Find the leaf of exactly this term. Unlike `find_term` it does not match prefixes
+    pub fn find_exact(&self, term: &str) -> Option<StaticNode> {
+        self.walk_exact(term, |_| {}).map(|link| self.node_at(link))
+    }
+
+    /// Like `find_exact`, but calls `on_step` on every node visited on the way down
+    pub fn find_exact_with<F>(&self, term: &str, mut on_step: F) -> Option<StaticNode>
+        where F: FnMut(&StaticNode)
+    {
+        self.walk_exact(term, |link| on_step(&self.node_at(link))).map(|link| self.node_at(link))
+    }
+
+    fn walk_exact<F: FnMut(Link)>(&self, term: &str, on_step: F) -> Option<Link> {
+        match self.format {
+            TrieFormat::Narrow => self.walk_exact_in(TrieFormat::Narrow, term, on_step),
+            TrieFormat::Wide => self.walk_exact_in(TrieFormat::Wide, term, on_step),
+            TrieFormat::Compact => self.walk_exact_in(TrieFormat::Compact, term, on_step),
+        }
+    }
+
+    #[inline(always)]
+    fn walk_exact_in<F: FnMut(Link)>(&self, format: TrieFormat, term: &str, mut on_step: F) -> Option<Link> {
+        let mut term = term.as_bytes();
+        let mut cursor = self.link_in(format, self.root_ptr);
+        loop {
+            on_step(cursor);
+            let label = cursor.label(self.term_buffer);
+            if !term.starts_with(label) {
+                return None;
+            }
+            term = &term[label.len()..];
+            if cursor.is_leaf() {
+                return if term.is_empty() { Some(cursor) } else { None };
+            }
+            // Exhausted term continues to its '\0' terminated leaf
+            let first_letter = if term.is_empty() { 0 } else { self.mode.first_letter(term) };
+            cursor = tryopt!(self.child(format, &cursor, first_letter));
+        }
+    }
+
+    /// Find the node of all terms starting with `term`. Unless `find_nearest`, `term` must end
+    /// at the end of a node's label
+    pub fn find_term(&self, term: &str, find_nearest: bool) -> Option<StaticNode> {
+        self.walk_term(term, find_nearest, |_| {}).map(|link| self.node_at(link))
+    }
+
+    /// Like `find_term`, but calls `on_step` on every node visited on the way down
+    pub fn find_term_with<F>(&self, term: &str, find_nearest: bool, mut on_step: F) -> Option<StaticNode>
+        where F: FnMut(&StaticNode)
+    {
+        self.walk_term(term, find_nearest, |link| on_step(&self.node_at(link))).map(|link| self.node_at(link))
+    }
+
+    fn walk_term<F: FnMut(Link)>(&self, term: &str, find_nearest: bool, on_step: F) -> Option<Link> {
+        match self.format {
+            TrieFormat::Narrow => self.walk_term_in(TrieFormat::Narrow, term, find_nearest, on_step),
+            TrieFormat::Wide => self.walk_term_in(TrieFormat::Wide, term, find_nearest, on_step),
+            TrieFormat::Compact => self.walk_term_in(TrieFormat::Compact, term, find_nearest, on_step),
+        }
+    }
+
+    #[inline(always)]
+    fn walk_term_in<F: FnMut(Link)>(&self, format: TrieFormat, term: &str, find_nearest: bool, mut on_step: F) -> Option<Link> {
+        let mut term = term.as_bytes();
+        let mut cursor = self.link_in(format, self.root_ptr);
+        loop {
+            // The terminating '\0' of leaves is not in the term buffer, it never matches anyway
+            let label = cursor.label(self.term_buffer);
+            trace!("looking for: '{}', cursor term: '{}', node: {:?}",
+                   String::from_utf8_lossy(term), String::from_utf8_lossy(label), cursor);
+            on_step(cursor);
+            let skip = self.mode.common_prefix_len(label, term);
+            if skip < term.len() && skip < label.len() {
+                // Term diverges from the label
+                return None;
+            } else if skip < term.len() {
+                term = &term[skip..];
+                let first_letter = self.mode.first_letter(term);
+                cursor = tryopt!(self.child(format, &cursor, first_letter));
+            } else if skip < label.len() {
+                // Term ends inside the label, the node's terms all extend it
+                return if find_nearest { Some(cursor) } else { None };
             } else {
-                return Some(&cursor);
+                return Some(cursor);
+            }
+        }
+    }
+}
+
+pub struct TrieEntry {
+    /// Number of edges from the root
+    pub depth: usize,
+    /// Whole term of the node. Leaf terms are without the terminating '\0'. Parts of chars
+    /// split by a byte trie are replaced by U+FFFD
+    pub term: String,
+    pub node: StaticNode,
+}
+
+pub struct TrieIter<'t, 'a: 't> {
+    trie: &'t StaticTrie<'a>,
+    stack: Vec<(StaticNode, usize, Vec<u8>)>,
+}
+
+impl<'t, 'a> Iterator for TrieIter<'t, 'a> {
+    type Item = TrieEntry;
+
+    fn next(&mut self) -> Option<TrieEntry> {
+        let (node, depth, mut term) = tryopt!(self.stack.pop());
+        term.extend_from_slice(node.link.label(self.trie.term_buffer));
+        // Push in reverse so that the smallest child is popped first
+        for child in self.trie.children(&node).into_iter().rev() {
+            self.stack.push((child, depth + 1, term.clone()));
+        }
+        // Whole terms of leaves are always valid, prefixes in a byte trie need not be
+        let term = String::from_utf8(term).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+        Some(TrieEntry { depth, term, node })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types::*;
+    use termbuf::TermBuf;
+    use postings::PlainEncoder;
+    use random::XorShift;
+    use nutrie::{StaticTrie,TrieMode,TrieFormat,PrefixPostings,PostingsEncoders,create_trie,terms_with_postings};
+
+    fn build_dict(words: &[&str], mode: TrieMode, format: TrieFormat) -> (Vec<u8>, usize, usize, usize) {
+        let (mut docs, mut tfs, mut positions) = (TermBuf::new(), TermBuf::new(), TermBuf::new());
+        let mut terms = Vec::new();
+        for (i, word) in words.iter().enumerate() {
+            let term_id = i as TermId + 1;
+            docs.add_doc(term_id, 1);
+            tfs.add_doc(term_id, 1);
+            positions.add_doc(term_id, 0);
+            terms.push(Term { term: word.to_string(), term_id });
+        }
+        terms.sort_by(|a, b| a.term.cmp(&b.term));
+
+        let mut dict = Vec::new();
+        let mut enc = PostingsEncoders {
+            docs: PlainEncoder::new(Vec::new()),
+            tfs: PlainEncoder::new(Vec::new()),
+            positions: PlainEncoder::new(Vec::new()),
+        };
+        let mut postings = (&mut docs, &mut tfs, &mut positions);
+        let (_, dict_size, root_ptr, terms_size) = create_trie(mode, format, PrefixPostings::default(), words.len() as TermId, terms_with_postings(&terms, &mut postings),
+                                                               &mut dict, &mut enc);
+        (dict, dict_size, root_ptr, terms_size)
+    }
+
+    #[test]
+    fn test_iter_and_find_exact() {
+        let words = ["band", "ban", "apple", "bandana", "apricot"];
+        let (buf, dict_size, root_ptr, terms_size) = build_dict(&words, TrieMode::Chars, TrieFormat::Narrow);
+        let dict = StaticTrie::new(&buf, dict_size, root_ptr, terms_size);
+
+        let leaves = dict.iter().filter(|e| e.node.is_leaf()).map(|e| e.term).collect::<Vec<_>>();
+        assert_eq!(leaves, vec!["apple", "apricot", "ban", "band", "bandana"]);
+
+        for word in &words {
+            let header = dict.find_exact(word).unwrap();
+            assert!(header.is_leaf());
+            assert_eq!(header.num_postings, 1);
+        }
+        assert!(dict.find_exact("ap").is_none());
+        assert!(dict.find_exact("bandanas").is_none());
+        // Diverges inside the label of "ban", "d" is a child of it
+        assert!(dict.find_term("bad", true).is_none());
+        assert_eq!(dict.find_term("ba", true), dict.find_term("ban", false));
+        assert!(dict.find_term("ba", false).is_none());
+        assert!(dict.find_exact("").is_none());
+    }
+
+    #[test]
+    fn test_one_letter_forks() {
+        // All children of the root are forks of one letter
+        let words = ["a", "ab", "b", "ba"];
+        let (buf, dict_size, root_ptr, terms_size) = build_dict(&words, TrieMode::Chars, TrieFormat::Narrow);
+        let dict = StaticTrie::new(&buf, dict_size, root_ptr, terms_size);
+        assert_eq!(dict.root().num_postings, 0);
+        assert_eq!(dict.find_term("a", false).unwrap().num_postings, 1);
+        assert_eq!(dict.find_exact("ba").unwrap().num_postings, 1);
+    }
+
+    #[test]
+    fn test_unicode() {
+        // é, è and ß share their first byte, e + U+0301 is a combining mark, emoji take 4 bytes
+        let words = ["é", "ée", "è", "ß", "e\u{301}", "e\u{301}t", "e", "😀", "😀😁", "😁", "中文", "中", "a\u{ff}"];
+        for &(mode, format) in &[(TrieMode::Chars, TrieFormat::Narrow), (TrieMode::Bytes, TrieFormat::Narrow),
+                                  (TrieMode::Chars, TrieFormat::Wide), (TrieMode::Bytes, TrieFormat::Compact)] {
+            let (buf, dict_size, root_ptr, terms_size) = build_dict(&words, mode, format);
+            let dict = StaticTrie::new(&buf, dict_size, root_ptr, terms_size).with_mode(mode).with_format(format);
+
+            let leaves = dict.iter().filter(|e| e.node.is_leaf()).map(|e| e.term).collect::<Vec<_>>();
+            let mut sorted = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+            sorted.sort();
+            assert_eq!(leaves, sorted, "{:?}", mode);
+
+            for word in &words {
+                assert!(dict.find_exact(word).is_some(), "{:?}: {}", mode, word);
+                assert!(dict.find_term(word, false).is_some(), "{:?}: {}", mode, word);
             }
-            // println!("");
+            assert!(dict.find_exact("😀😀").is_none());
+            assert!(dict.find_exact("中文字").is_none());
+            assert!(dict.find_term("ü", true).is_none());
+            // Prefixes are prefixes of chars, the combining mark is a char of its own
+            let e = dict.find_term("e", true).unwrap();
+            let under_e = dict.iter().filter(|entry| entry.term.starts_with('e') && entry.node.is_leaf()).count();
+            assert_eq!(under_e, 3);
+            assert!(!e.is_leaf());
+            assert!(dict.find_term("😀", false).is_some_and(|n| !n.is_leaf()));
+        }
+    }
+
+    #[test]
+    fn test_long_labels() {
+        // Labels longer than the narrow format allows, shared by forks and leaves
+        let long = "x".repeat(100_000);
+        let words = [format!("{}a", long), format!("{}b", long), long.clone(), "a".to_string(), "ab".to_string(), "abc".to_string()];
+        let words = words.iter().map(|w| w.as_str()).collect::<Vec<_>>();
+        for &format in &[TrieFormat::Wide, TrieFormat::Compact] {
+            check_long_labels(&words, &long, format);
+        }
+    }
+
+    fn check_long_labels(words: &[&str], long: &str, format: TrieFormat) {
+        let (buf, dict_size, root_ptr, terms_size) = build_dict(words, TrieMode::Chars, format);
+        let dict = StaticTrie::new(&buf, dict_size, root_ptr, terms_size).with_format(format);
+
+        let leaves = dict.iter().filter(|e| e.node.is_leaf()).map(|e| e.term).collect::<Vec<_>>();
+        let mut sorted = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        sorted.sort();
+        assert_eq!(leaves, sorted);
+        for word in words {
+            assert_eq!(dict.find_exact(word).unwrap().num_postings, 1);
+        }
+        let fork = dict.find_term(&long[..70_000], true).unwrap();
+        assert_eq!((fork.num_children, fork.num_postings), (3, 1));
+        assert_eq!(dict.label(&fork).len(), 100_000);
+        assert!(dict.find_exact(&long[1..]).is_none());
+    }
+
+    /// Build the words in narrow and compact format, compare their nodes and lookups of every
+    /// prefix of the words. Returns sizes of both dictionaries
+    fn compare_compact(words: &[&str]) -> (usize, usize) {
+        let (narrow, narrow_size, narrow_root, terms_size) = build_dict(words, TrieMode::Chars, TrieFormat::Narrow);
+        let (compact, compact_size, compact_root, _) = build_dict(words, TrieMode::Chars, TrieFormat::Compact);
+        let narrow = StaticTrie::new(&narrow, narrow_size, narrow_root, terms_size);
+        let compact = StaticTrie::new(&compact, compact_size, compact_root, terms_size).with_format(TrieFormat::Compact);
+
+        let entries = |dict: &StaticTrie| dict.iter().map(|e| {
+            (e.term, e.depth, e.node.term_id, e.node.num_postings, e.node.postings_ptr, e.node.num_children)
+        }).collect::<Vec<_>>();
+        assert_eq!(entries(&compact), entries(&narrow));
+        let prefixes = words.iter().flat_map(|w| (0..=w.len()).map(move |i| w[..i].to_string()));
+        for query in prefixes.chain(words.iter().map(|w| format!("{}z", w))) {
+            assert_eq!(compact.find_term(&query, true).map(|n| n.term_id), narrow.find_term(&query, true).map(|n| n.term_id), "{}", query);
+            assert_eq!(compact.find_exact(&query).map(|n| n.term_id), narrow.find_exact(&query).map(|n| n.term_id), "{}", query);
+        }
+        (narrow_size, compact_size)
+    }
+
+    #[test]
+    fn test_compact_format() {
+        // Fanouts of the root and of "k" go over the inline limit, offsets take several bytes
+        let mut words = (0..300).map(|i| format!("{}{}", (b'a' + (i % 26) as u8) as char, i)).collect::<Vec<_>>();
+        words.extend((0..20).map(|i| format!("k{}", (b'a' + i as u8) as char)));
+        let (narrow_size, compact_size) = compare_compact(&words.iter().map(|w| w.as_str()).collect::<Vec<_>>());
+        assert!(compact_size * 2 < narrow_size, "{} vs {}", compact_size, narrow_size);
+
+        // Short words over a small alphabet fork a lot
+        let mut rng = XorShift::new(1);
+        for _ in 0..20 {
+            let mut words = (0..rng.range(1, 100)).map(|_| {
+                (0..rng.range(1, 6)).map(|_| (b'a' + rng.below(5) as u8) as char).collect::<String>()
+            }).collect::<Vec<_>>();
+            words.sort();
+            words.dedup();
+            compare_compact(&words.iter().map(|w| w.as_str()).collect::<Vec<_>>());
         }
     }
 }
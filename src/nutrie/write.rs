@@ -1,4 +1,4 @@
-use std::{str,slice,cmp,mem};
+use std::{slice,cmp,mem};
 use std::io::{Write};
 use std::rc::{Rc,Weak};
 use std::cell::{RefCell,Ref,RefMut};
@@ -7,13 +7,18 @@ use std::iter::{Iterator};
 
 use types::*;
 use util::*;
-use nutrie::TrieNodeHeader;
+use nutrie::{TrieNodeHeader,WideLabel,TrieMode,TrieFormat,PrefixPostings,COMPACT_LEAF,COMPACT_INLINE_FANOUT};
 use postings::{VecPostings,Postings,PostingsStore,SequenceStorage,SequenceEncoder,RawCursor,MergerWithoutDuplicatesUnrolled};
 
 
+/// Bytes needed for `x`, at least one
+fn uint_width(x: u64) -> usize {
+    cmp::max(1, (64 - x.leading_zeros() as usize).div_ceil(8))
+}
+
 fn delta_encode(xs: &[DocId]) -> Vec<DocId> {
     let mut v = Vec::new();
-    if xs.len() < 1 {
+    if xs.is_empty() {
         v.extend_from_slice(xs);
     } else {
         let tail = xs[1..].iter();
@@ -25,17 +30,18 @@ fn delta_encode(xs: &[DocId]) -> Vec<DocId> {
 
 #[derive(Clone)]
 pub struct WrittenTerm {
-    term: String,
+    /// Whole term of the node, not necessarily valid UTF-8 in `TrieMode::Bytes`
+    term: Vec<u8>,
     term_ptr: usize,
     term_id: TermId,
 }
 
 impl WrittenTerm {
-    fn new(term: &str, term_id: TermId, term_ptr: usize) -> WrittenTerm {
+    fn new(term: &[u8], term_id: TermId, term_ptr: usize) -> WrittenTerm {
         WrittenTerm {
             term: term.into(),
-            term_ptr: term_ptr,
-            term_id: term_id,
+            term_ptr,
+            term_id,
         }
     }
 }
@@ -46,10 +52,34 @@ pub struct PostingsEncoders<DocsEncoder, TfsEncoder, PosEncoder> {
     pub positions: PosEncoder,
 }
 
-pub fn create_trie<PS, W, DE, TE, PE>(mut term_serial: TermId, terms: &[Term], postings_store: &mut PS,
-                                          dict_out: &mut W, enc: &mut PostingsEncoders<DE, TE, PE>)
+/// Pair sorted terms with their postings taken from the store
+pub fn terms_with_postings<'a, PS: PostingsStore>(terms: &'a [Term], postings_store: &'a mut PS)
+    -> impl Iterator<Item = (Term, VecPostings)> + 'a
+{
+    terms.iter().map(move |t| {
+        let postings = postings_store.get_postings(t.term_id).expect("Every term must have postings");
+        (t.clone(), postings)
+    })
+}
+
+/// Positions in the written dictionary and postings while flushing nodes
+struct FlushState {
+    mode: TrieMode,
+    format: TrieFormat,
+    prefix_postings: PrefixPostings,
+    dict_ptr: usize,
+    postings_ptr: DocId,
+    last_tf: DocId,
+}
+
+/// Write the trie of terms and their postings. Terms must come sorted and their postings
+/// must have non-cumulated tfs. `term_serial` must be at least the maximum term id, new
+/// inner nodes get ids above it. Terms must fit the format, see `TrieFormat::for_terms`.
+/// Inner nodes get their merged postings written as `prefix_postings` tells.
+pub fn create_trie<I, W, DE, TE, PE>(mode: TrieMode, format: TrieFormat, prefix_postings: PrefixPostings, mut term_serial: TermId, terms: I,
+                                     dict_out: &mut W, enc: &mut PostingsEncoders<DE, TE, PE>)
     -> (Vec<WrittenTerm>, usize, usize, usize)
-    where PS: PostingsStore,
+    where I: IntoIterator<Item = (Term, VecPostings)>,
           W: Write,
           DE: SequenceEncoder,
           TE: SequenceEncoder,
@@ -58,7 +88,7 @@ pub fn create_trie<PS, W, DE, TE, PE>(mut term_serial: TermId, terms: &[Term], p
     let mut new_terms = Vec::<WrittenTerm>::new();
 
     // Create 2 dummy roots - because you need 2 node pointers - parent and current
-    let root_term = WrittenTerm::new("", 0, 0);
+    let root_term = WrittenTerm::new(b"", 0, 0);
     let root1 = TrieNode::new(None, root_term.clone(), None);
     let root2 = TrieNode::new(Some(root1.clone()), root_term, None);
     root1.clone().add_child(root2.clone());
@@ -67,22 +97,20 @@ pub fn create_trie<PS, W, DE, TE, PE>(mut term_serial: TermId, terms: &[Term], p
     let mut current: TrieNode = root2.clone();
 
     let mut term_ptr = 0;
-    let mut dict_ptr = 0;
-    let mut postings_ptr = 0;
-    let mut last_tf = 0;
+    let mut term_buffer = Vec::new();
+    let mut state = FlushState { mode, format, prefix_postings, dict_ptr: 0, postings_ptr: 0, last_tf: 0 };
 
-    for &Term{ref term, term_id} in terms.iter() {
-        // TODO add null termination to terminals
-        let nullterm = format!("{}{}", term, "\0");
+    for (Term{term, term_id}, child_postings) in terms {
+        let mut nullterm = term.clone().into_bytes();
+        nullterm.push(0);
 
-        let prefix_len = common_prefix_len(&current.borrow().t.term, &nullterm);
-        let child_postings = postings_store.get_postings(term_id);
+        let prefix_len = mode.common_prefix_len(&current.borrow().t.term, &nullterm);
 
         //println!("IT {} {} {}", current.borrow().t.term, term, prefix_len);
 
         // align parent and current pointers
         while prefix_len < parent.borrow().t.term.len() {
-            current.flush(&parent, &mut dict_ptr, &mut postings_ptr, &mut last_tf, dict_out, enc);
+            current.flush(&parent, &mut state, dict_out, enc);
             current = parent.clone();
             parent = parent.parent().unwrap();
         }
@@ -92,7 +120,7 @@ pub fn create_trie<PS, W, DE, TE, PE>(mut term_serial: TermId, terms: &[Term], p
             // NOTE: With null terminating strings this must be unreachable (except root)
             assert_eq!(current.term_id(), 0);
         } else if prefix_len == parent.term_len() {
-            current.flush(&parent, &mut dict_ptr, &mut postings_ptr, &mut last_tf, dict_out, enc);
+            current.flush(&parent, &mut state, dict_out, enc);
         } else if prefix_len > parent.term_len() {
             //let parent_term_ptr = current.borrow().term_ptr;
             term_serial += 1;
@@ -110,7 +138,7 @@ pub fn create_trie<PS, W, DE, TE, PE>(mut term_serial: TermId, terms: &[Term], p
             );
 
             // Flush with fork_node as a new parent
-            current.flush(&fork_node, &mut dict_ptr, &mut postings_ptr, &mut last_tf, dict_out, enc);
+            current.flush(&fork_node, &mut state, dict_out, enc);
 
             parent = current.clone();
             current = fork_node.clone();
@@ -124,36 +152,35 @@ pub fn create_trie<PS, W, DE, TE, PE>(mut term_serial: TermId, terms: &[Term], p
         new_terms.push(new_term.clone());
         // Don't write the terminating '\0' character. Use term.len(), not nullterm.len()
         term_ptr += term.len();
+        term_buffer.extend_from_slice(term.as_bytes());
 
         let parent_clone = parent.clone();
         current = parent.add_child(TrieNode::new(
             Some(parent_clone),
             new_term,
-            child_postings,
+            Some(child_postings),
         ));
     }
 
     while let Some(parent_parent) = parent.parent() {
-        current.flush(&parent, &mut dict_ptr, &mut postings_ptr, &mut last_tf, dict_out, enc);
+        current.flush(&parent, &mut state, dict_out, enc);
         current = parent.clone();
         parent = parent_parent;
     }
 
-    let root_ptr = dict_ptr;
+    let root_ptr = state.dict_ptr;
     assert!(current.parent().unwrap().term_id() == 0);
     // Flush root2 node
-    current.flush(&parent, &mut dict_ptr, &mut postings_ptr, &mut last_tf, dict_out, enc);
+    current.flush(&parent, &mut state, dict_out, enc);
 
     // Don't forget to write last_tf so that differences tfs[i + 1] - tfs[i] work for all doc
     // positions
-    let _ = enc.tfs.write(last_tf).unwrap();
+    let _ = enc.tfs.write(state.last_tf).unwrap();
 
 
-    for t in terms.iter() {
-        dict_out.write(&t.term.as_bytes()).unwrap();
-    }
+    dict_out.write_all(&term_buffer).unwrap();
 
-    (new_terms, dict_ptr, root_ptr, term_ptr)
+    (new_terms, state.dict_ptr, root_ptr, term_ptr)
 }
 
 // 't: 'n means that terms ('t) can live longer than nodes ('n) It is needed so that root term can
@@ -165,6 +192,10 @@ struct _TrieNode {
     t: WrittenTerm,
     pointer_in_dictbuf: Option<usize>,
     postings: Option<VecPostings>,
+    /// Postings a prefix search at the node merges, its own if written
+    num_lists: u64,
+    /// Postings of descendants passed on unmerged to the parent, when the node has none
+    lists: Vec<VecPostings>,
     parent: Option<TrieNodeWeak>,
     children: Vec<TrieNodeRef>,
 }
@@ -179,9 +210,11 @@ impl TrieNode {
         //    println!("---");
         //}
         TrieNode(Rc::new(RefCell::new(_TrieNode {
-            t: t,
+            t,
             pointer_in_dictbuf: None,
-            postings: postings,
+            postings,
+            num_lists: 0,
+            lists: Vec::new(),
             parent: parent.map(|p| Rc::downgrade(&p.clone())),
             children: Vec::new(),
         })))
@@ -193,10 +226,7 @@ impl TrieNode {
     }
 
     fn parent(&self) -> Option<TrieNode> {
-        match self.borrow().parent {
-            Some(ref weak_link) => Some(TrieNode(weak_link.upgrade().unwrap())),
-            None => None,
-        }
+        self.borrow().parent.as_ref().map(|weak_link| TrieNode(weak_link.upgrade().unwrap()))
     }
 
     fn term_id(&self) -> TermId {
@@ -221,11 +251,11 @@ impl TrieNode {
         TrieNode(borrow.0)
     }
 
-    fn borrow(&self) -> Ref<_TrieNode> {
+    fn borrow(&self) -> Ref<'_, _TrieNode> {
         self.0.borrow()
     }
 
-    fn borrow_mut(&self) -> RefMut<_TrieNode> {
+    fn borrow_mut(&self) -> RefMut<'_, _TrieNode> {
         self.0.borrow_mut()
     }
 
@@ -237,23 +267,53 @@ impl TrieNode {
         }
     }
 
-    fn create_child_pointers(&self) -> Vec<u32> {
+    fn create_child_pointers(&self) -> Vec<u64> {
         self.borrow().children.iter().map(|ch| {
-            ch.borrow().pointer_in_dictbuf.expect("This node must be written by now") as u32
+            ch.borrow().pointer_in_dictbuf.expect("This node must be written by now") as u64
         }).collect()
     }
 
-    fn create_child_index(&self) -> Vec<u32> {
+    fn create_child_index(&self, mode: TrieMode) -> Vec<u32> {
         let prefix = &self.borrow().t.term;
         self.borrow().children.iter().map(|ch| {
             let ch_borrow = ch.borrow();
             let suffix = &ch_borrow.t.term[prefix.len()..];
-            // println!("prefix='{}',   term='{}', suffix='{}'", prefix, ch_borrow.t.term, suffix);
-            assert!(suffix.len() > 0);
-            first_letter(suffix)
+            assert!(!suffix.is_empty());
+            mode.first_letter(suffix)
         }).collect()
     }
 
+    /// Node in `TrieFormat::Compact` written at `position`, with its children
+    fn encode_compact(&self, header: &TrieNodeHeader, label: &WideLabel, mode: TrieMode, position: usize) -> Vec<u8> {
+        let num_children = self.borrow().children.len();
+        let (ptr_width, length_width) = (uint_width(label.term_ptr), uint_width(label.term_length));
+        let flags = if num_children == 0 { COMPACT_LEAF } else { 0 };
+        let mut out = vec![flags | ((ptr_width - 1) << 1 | (length_width - 1) << 4) as u8];
+        out.extend_from_slice(&label.term_ptr.to_le_bytes()[..ptr_width]);
+        out.extend_from_slice(&label.term_length.to_le_bytes()[..length_width]);
+        if num_children > 0 {
+            write_varint(&mut out, num_children as u64);
+            let children_index = self.create_child_index(mode);
+            let offsets = self.create_child_pointers().iter().map(|&ptr| position as u64 - ptr).collect::<Vec<_>>();
+            let width = uint_width(*offsets.iter().max().unwrap());
+            out.push(width as u8);
+            for offset in &offsets {
+                out.extend_from_slice(&offset.to_le_bytes()[..width]);
+            }
+            for &letter in &children_index {
+                if num_children <= COMPACT_INLINE_FANOUT {
+                    write_varint(&mut out, letter as u64);
+                } else {
+                    out.extend_from_slice(&letter.to_le_bytes());
+                }
+            }
+        }
+        for &x in &[header.term_id as u64, header.num_postings, header.postings_ptr] {
+            write_varint(&mut out, x);
+        }
+        out
+    }
+
     fn write_postings<DE, TE, PE>(&self, enc: &mut PostingsEncoders<DE, TE, PE>, postings_ptr: &mut DocId, last_tf: &mut DocId)
         where
           DE: SequenceEncoder,
@@ -292,67 +352,123 @@ impl TrieNode {
         }
     }
 
+    /// Cumulate tfs of postings which are not written, like `write_postings` does, so that
+    /// the parent can merge them
+    fn cumulate_tfs(&self) {
+        if let Some(ref mut postings) = self.borrow_mut().postings {
+            let mut cum = 0;
+            for ptr in &mut postings.tfs {
+                let tf = *ptr;
+                *ptr = cum;
+                cum += tf;
+            }
+            postings.tfs.push(cum);
+        }
+    }
 
-    fn flush<W, DE, TE, PE>(&self, parent: &Self, dict_ptr: &mut usize, postings_ptr: &mut DocId, last_tf: &mut DocId,
-                            dict_out: &mut W, enc: &mut PostingsEncoders<DE, TE, PE>)
+    fn flush<W, DE, TE, PE>(&self, parent: &Self, state: &mut FlushState, dict_out: &mut W, enc: &mut PostingsEncoders<DE, TE, PE>)
         where W: Write,
               DE: SequenceEncoder,
               TE: SequenceEncoder,
               PE: SequenceEncoder
     {
         // println!("flushing node with {} children: term: '{}'", self_borrow.children.len(), self.term());
-        if self.borrow().children.len() > 0 {
-            let merged_postings = {
-                let selfb = self.borrow();
-                assert!(selfb.children.len() <= u32::max_value() as usize);
-
-                // Need to store actual borrows first
-                let borrows = selfb.children.iter().map(|p| { p.borrow() }).collect::<Vec<_>>();
-
-                let mut postings_to_merge = Vec::new();
-                for child in &borrows {
-                    if let Some(ref p) = child.postings {
-                        postings_to_merge.push(RawCursor::new(Postings {
-                            docs: (&p.docs).to_sequence(),
-                            tfs: (&p.tfs).to_sequence(),
-                            positions: (&p.positions).to_sequence(),
-                        }));
+        let is_root = self.term_id() == 0;
+        let num_lists = self.borrow().children.iter().map(|child| child.borrow().num_lists).sum();
+        if !self.borrow().children.is_empty() {
+            let max_postings = self.borrow().children.iter().map(|child| {
+                let child = child.borrow();
+                child.postings.iter().chain(&child.lists).map(|p| p.docs.len() as u64).sum::<u64>()
+            }).sum();
+            if state.prefix_postings.may_store(is_root, max_postings, num_lists) {
+                let merged_postings = {
+                    let selfb = self.borrow();
+                    assert!(selfb.children.len() <= u32::MAX as usize);
+
+                    // Need to store actual borrows first
+                    let borrows = selfb.children.iter().map(|p| { p.borrow() }).collect::<Vec<_>>();
+
+                    let mut postings_to_merge = Vec::new();
+                    for child in &borrows {
+                        for p in child.postings.iter().chain(&child.lists) {
+                            postings_to_merge.push(RawCursor::new(Postings {
+                                docs: (&p.docs).to_sequence(),
+                                tfs: (&p.tfs).to_sequence(),
+                                positions: (&p.positions).to_sequence(),
+                            }));
+                        }
                     }
+                    MergerWithoutDuplicatesUnrolled::new(postings_to_merge).collect()
+                };
+                self.borrow_mut().postings = Some(merged_postings);
+            } else if state.prefix_postings.may_merge(parent.term_id() == 0) {
+                // Left for the parent to merge
+                let mut lists = Vec::new();
+                for child in &self.borrow().children {
+                    let mut child = child.borrow_mut();
+                    lists.extend(child.postings.take());
+                    lists.append(&mut child.lists);
                 }
-                MergerWithoutDuplicatesUnrolled::new(postings_to_merge).collect()
-            };
-            self.borrow_mut().postings = Some(merged_postings);
+                self.borrow_mut().lists = lists;
+            }
         }
 
-        let dict_position = *dict_ptr;
+        // Terms always have postings, inner nodes as the policy tells
+        let stored = if self.borrow().children.is_empty() {
+            !is_root
+        } else {
+            self.postings_len() > 0 && state.prefix_postings.stores(self.postings_len() as u64, num_lists)
+        };
+        self.borrow_mut().num_lists = if stored { 1 } else { num_lists };
+
+        let dict_position = state.dict_ptr;
         let prefix = &parent.borrow().t.term;
 
         // NOTE aligning is not needed when Header, child index and child pointers are aligned
         // to repr(C) (autoalign)
-        let header = TrieNodeHeader::from_trienode(TrieNode(self.0.clone()), prefix, *postings_ptr);
-        *dict_ptr += dict_out.write(header.to_bytes()).unwrap();
+        let (mut header, wide_label) = TrieNodeHeader::from_trienode(TrieNode(self.0.clone()), prefix, state.postings_ptr, state.format);
+        if !stored {
+            header.num_postings = 0;
+        }
+        if state.format == TrieFormat::Compact {
+            let node = self.encode_compact(&header, &wide_label, state.mode, dict_position);
+            state.dict_ptr += dict_out.write(&node).unwrap();
+        } else {
+            state.dict_ptr += dict_out.write(header.to_bytes()).unwrap();
+            if state.format == TrieFormat::Wide {
+                state.dict_ptr += dict_out.write(typed_to_bytes(slice::from_ref(&wide_label))).unwrap();
+            }
+        }
 
-        if self.borrow().children.len() > 0 {
+        if !self.borrow().children.is_empty() && state.format != TrieFormat::Compact {
             // TODO assert that children_index and child_pointers are in ascending order
-            let children_index = self.create_child_index();
+            let children_index = self.create_child_index(state.mode);
             let child_pointers = self.create_child_pointers();
 
-            *dict_ptr += dict_out.write(typed_to_bytes(&children_index)).unwrap();
-            *dict_ptr += dict_out.write(typed_to_bytes(&child_pointers)).unwrap();
-            *dict_ptr += dict_out.write(&[0,8][..align_to(*dict_ptr, mem::align_of::<TrieNodeHeader>())]).unwrap();
+            state.dict_ptr += dict_out.write(typed_to_bytes(&children_index)).unwrap();
+            if state.format == TrieFormat::Narrow {
+                assert!(state.dict_ptr <= u32::MAX as usize, "Dictionary exceeds the narrow trie format");
+                let child_pointers = child_pointers.iter().map(|&ptr| ptr as u32).collect::<Vec<_>>();
+                state.dict_ptr += dict_out.write(typed_to_bytes(&child_pointers)).unwrap();
+            } else {
+                state.dict_ptr += dict_out.write(&[0; 8][..align_to(state.dict_ptr, mem::align_of::<u64>())]).unwrap();
+                state.dict_ptr += dict_out.write(typed_to_bytes(&child_pointers)).unwrap();
+            }
+            state.dict_ptr += dict_out.write(&[0,8][..align_to(state.dict_ptr, mem::align_of::<TrieNodeHeader>())]).unwrap();
         }
 
-        // Root node won't be written
-        if self.term_id() != 0 {
+        if stored {
             assert!(self.postings_len() > 0);
-            self.write_postings(enc, postings_ptr, last_tf);
+            self.write_postings(enc, &mut state.postings_ptr, &mut state.last_tf);
+        } else {
+            self.cumulate_tfs();
         }
 
-        // Because root node won't be written, remove all postings from 1-character term prefixes.
-        // They will not be needed anymore. This will save some memory during indexing
-        let termlen = self.borrow().t.term.chars().count();
-        if self.borrow().children.len() > 0 && termlen == 1 {
+        // Postings not merged into the parent are not needed anymore. This saves some memory
+        // during indexing
+        if !state.prefix_postings.may_merge(parent.term_id() == 0) {
             self.borrow_mut().postings = None;
+            self.borrow_mut().lists.clear();
         }
 
         self.borrow_mut().children.clear();
@@ -380,19 +496,22 @@ impl TrieNodeHeader {
         unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<TrieNodeHeader>()) }
     }
 
-    fn from_trienode(n: TrieNode, prefix: &str, postings_ptr: DocId) -> TrieNodeHeader {
+    fn from_trienode(n: TrieNode, prefix: &[u8], postings_ptr: DocId, format: TrieFormat) -> (TrieNodeHeader, WideLabel) {
         let term = &n.borrow().t.term[prefix.len()..];
-        // TODO Handle longer strings by truncating
-        assert!(term.len() < u16::max_value() as usize);
-        assert!(n.postings_len() > 0);
+        let term_ptr = n.term_ptr() + prefix.len();
+        if format == TrieFormat::Narrow {
+            assert!(term.len() < u16::MAX as usize && term_ptr <= u32::MAX as usize, "Term exceeds the narrow trie format");
+        }
 
-        TrieNodeHeader {
-            postings_ptr: postings_ptr,
-            term_ptr: (n.term_ptr() + prefix.len()) as u32,
+        let header = TrieNodeHeader {
+            postings_ptr,
+            term_ptr: term_ptr.min(u32::MAX as usize) as u32,
             term_id: n.borrow().t.term_id,
-            term_length: term.len() as u16,
+            // Leaves count their '\0', so only empty labels saturate to 0
+            term_length: term.len().min(u16::MAX as usize) as u16,
             num_postings: n.postings_len() as u64,
             num_children: n.borrow().children.len() as u32,
-        }
+        };
+        (header, WideLabel { term_ptr: term_ptr as u64, term_length: term.len() as u64 })
     }
 }
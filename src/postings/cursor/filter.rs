@@ -0,0 +1,92 @@
+use types::*;
+use bitmap::Bitmap;
+use postings::{VecPostings,PostingsCursor};
+
+/// Skips documents of the wrapped cursor which are marked deleted
+pub struct Filter<'a, C: PostingsCursor> {
+    cursor: C,
+    deleted: &'a Bitmap,
+}
+
+impl<'a, C: PostingsCursor> Filter<'a, C> {
+    pub fn new(cursor: C, deleted: &'a Bitmap) -> Self {
+        Filter {
+            cursor,
+            deleted,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cursor
+    }
+}
+
+impl<'a, C: PostingsCursor> PostingsCursor for Filter<'a, C> {
+    type DS = C::DS;
+    type TS = C::TS;
+    type PS = C::PS;
+
+    unsafe fn current(&self) -> DocId {
+        self.cursor.current()
+    }
+
+    /// Upper bound, deleted documents are not known in advance
+    fn remains(&self) -> usize {
+        self.cursor.remains()
+    }
+
+    fn advance(&mut self) -> Option<DocId> {
+        loop {
+            let doc_id = tryopt!(self.cursor.advance());
+            if !self.deleted.contains(doc_id) {
+                return Some(doc_id);
+            }
+        }
+    }
+
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        let next_doc = tryopt!(self.cursor.advance_to(doc_id));
+        if self.deleted.contains(next_doc) {
+            self.advance()
+        } else {
+            Some(next_doc)
+        }
+    }
+
+    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
+        self.cursor.catch_up(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postings::{RawCursor,Postings,SequenceStorage};
+
+    #[test]
+    fn test_filter() {
+        let ps = VecPostings {
+            docs: vec![1, 2, 3, 5, 8],
+            tfs: vec![0, 1, 2, 3, 4, 5],
+            positions: vec![10, 20, 30, 50, 80],
+        };
+        let cursor = RawCursor::new(Postings {
+            docs: (&ps.docs).to_sequence(),
+            tfs: (&ps.tfs).to_sequence(),
+            positions: (&ps.positions).to_sequence(),
+        });
+        let mut deleted = Bitmap::new();
+        deleted.insert(1);
+        deleted.insert(3);
+        deleted.insert(5);
+
+        let mut filter = Filter::new(cursor, &deleted);
+        assert_eq!(filter.advance(), Some(2));
+        assert_eq!(filter.advance_to(3), Some(8));
+        let mut result = VecPostings { docs: Vec::new(), tfs: Vec::new(), positions: Vec::new() };
+        filter.catch_up(&mut result);
+        assert_eq!(result.docs, vec![8]);
+        assert_eq!(result.positions, vec![80]);
+        assert_eq!(filter.advance(), None);
+    }
+}
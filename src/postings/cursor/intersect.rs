@@ -1,75 +1,190 @@
 use types::*;
 use postings::{PostingsCursor,VecPostings};
-use std::usize;
 
-pub struct IntersectUnrolled<C: PostingsCursor> {
-    cursors: Vec<C>
+/// Cursors at least this many times longer than the shortest one are skipped through with
+/// `advance_to`, shorter ones are just advanced
+const GALLOP_RATIO: usize = 8;
+
+/// More than two cursors are intersected set versus set when all but the shortest one are at
+/// least this many times longer
+const SVS_RATIO: usize = 64;
+
+/// Documents present in all of the cursors. No cursors match no documents.
+///
+/// By default the shortest cursor proposes candidates and the others are probed for them in
+/// ascending order of their size, so that the candidate is rejected as early as possible.
+/// Cursors stay on the match. When the shortest cursor is tiny next to all others, they are
+/// intersected set versus set instead: the candidates of the shortest cursor are filtered by
+/// each of the others in turn, which is read once for the few candidates left, and matches
+/// are handed out of postings read ahead. Either way postings of a match are caught up in the
+/// order the cursors were given in.
+pub struct Intersect<C: PostingsCursor> {
+    cursors: Vec<C>,
+    /// Indices of cursors in ascending order of their size
+    probes: Vec<usize>,
+    gallop: Vec<bool>,
+    current: DocId,
+    size: usize,
+    initial: Vec<usize>,
+    matches: usize,
+    exhausted: bool,
+    svs: bool,
+    /// Matches found set versus set, on the first advance
+    read_ahead: Option<ReadAhead>,
 }
 
-impl <C: PostingsCursor> IntersectUnrolled<C> {
+/// Postings of documents read ahead from each cursor, in the order the cursors were given in
+struct ReadAhead {
+    docs: Vec<DocId>,
+    next: usize,
+    postings: Vec<VecPostings>,
+    /// For each cursor, candidates it had with the start of their postings: the index of the
+    /// first entry and of the first position
+    starts: Vec<Vec<(DocId, usize, usize)>>,
+    /// For each cursor, the first of `starts` not handed out yet
+    next_starts: Vec<usize>,
+}
+
+impl<C: PostingsCursor> Intersect<C> {
+    /// Intersect the cursors, set versus set if their sizes favor it
     pub fn new(cursors: Vec<C>) -> Self {
-        IntersectUnrolled {
-            cursors: cursors
+        let mut initial = cursors.iter().map(|c| c.remains()).collect::<Vec<_>>();
+        initial.sort();
+        let svs = initial.len() > 2 && initial[1] >= initial[0].saturating_mul(SVS_RATIO).max(1);
+        Self::with_svs(cursors, svs)
+    }
+
+    /// Like `new`, but intersecting set versus set or not regardless of the cursors' sizes
+    pub fn with_svs(cursors: Vec<C>, svs: bool) -> Self {
+        let initial = cursors.iter().map(|c| c.remains()).collect::<Vec<_>>();
+        let size = initial.iter().cloned().min().unwrap_or(0);
+        let mut probes = (0..cursors.len()).collect::<Vec<_>>();
+        probes.sort_by_key(|&i| initial[i]);
+        let gallop = initial.iter().map(|&n| n >= size.saturating_mul(GALLOP_RATIO).max(1)).collect();
+        Intersect {
+            exhausted: cursors.is_empty(),
+            cursors,
+            probes,
+            gallop,
+            current: 0,
+            size,
+            initial,
+            matches: 0,
+            svs,
+            read_ahead: None,
         }
     }
 
-    pub fn collect(&mut self) -> VecPostings {
-        let mut result = VecPostings {
-            docs: Vec::new(),
-            tfs: Vec::new(),
-            positions: Vec::new(),
-        };
+    pub fn matches(&self) -> usize {
+        self.matches
+    }
 
-        let mut current_doc_id = 0;
-        'intersect: loop {
-            for cur in &mut self.cursors {
-                if let Some(doc_id) = cur.advance() {
-                    // Start next iteration alignment with maximum doc id
-                    if doc_id > current_doc_id {
-                        current_doc_id = doc_id;
-                    }
-                } else {
-                    // This cursor is depleted and thus it can't produce no more matches
-                    break 'intersect;
-                }
-            }
+    /// Number of documents each cursor went past without them being matched
+    pub fn skip_counts(&self) -> Vec<usize> {
+        self.cursors.iter().zip(&self.initial).map(|(c, &initial)| {
+            (initial - c.remains()).saturating_sub(self.matches)
+        }).collect()
+    }
 
-            'align: loop {
-                for cur in &mut self.cursors {
-                    if let Some(doc_id) = cur.advance_to(current_doc_id) {
-                        if doc_id > current_doc_id {
-                            current_doc_id = doc_id;
-                            continue 'align;
-                        }
-                    } else {
-                        break 'intersect;
-                    }
-                }
-                break 'align;
-            }
+    /// Move the cursor to the first document not below the target. The cursor must not be
+    /// past the target already
+    fn seek(&mut self, i: usize, target: DocId) -> Option<DocId> {
+        let cursor = &mut self.cursors[i];
+        if self.gallop[i] {
+            return cursor.advance_to(target);
+        }
+        let mut doc_id = unsafe { cursor.current() };
+        while doc_id < target {
+            doc_id = cursor.advance()?;
+        }
+        Some(doc_id)
+    }
 
-            for cur in &mut self.cursors {
-                let _ = cur.catch_up(&mut result);
+    /// Read all matches ahead, filtering the documents of the shortest cursor by the others
+    /// one after the other
+    fn read_ahead(&mut self) -> ReadAhead {
+        let mut postings = self.cursors.iter().map(|_| VecPostings { docs: Vec::new(), tfs: Vec::new(), positions: Vec::new() }).collect::<Vec<_>>();
+        let mut starts = vec![Vec::new(); self.cursors.len()];
+        let mut docs = Vec::new();
+        let first = self.probes[0];
+        while let Some(doc_id) = self.cursors[first].advance() {
+            starts[first].push((doc_id, postings[first].docs.len(), postings[first].positions.len()));
+            self.cursors[first].catch_up(&mut postings[first]);
+            docs.push(doc_id);
+        }
+        for k in 1..self.probes.len() {
+            let i = self.probes[k];
+            let mut left = Vec::new();
+            let mut at = if docs.is_empty() { None } else { self.cursors[i].advance() };
+            for &candidate in &docs {
+                match at {
+                    Some(doc_id) if doc_id < candidate => at = self.seek(i, candidate),
+                    Some(_) => {}
+                    None => break,
+                }
+                if at == Some(candidate) {
+                    starts[i].push((candidate, postings[i].docs.len(), postings[i].positions.len()));
+                    self.cursors[i].catch_up(&mut postings[i]);
+                    left.push(candidate);
+                }
             }
+            docs = left;
         }
-
-        result
+        ReadAhead { docs, next: 0, postings, starts, next_starts: vec![0; self.cursors.len()] }
     }
-}
 
-pub struct Intersect<C: PostingsCursor> {
-    cursors: Vec<C>,
-    current: DocId,
-    size: usize,
-}
+    /// Next match not below `min`
+    fn next_match(&mut self, min: DocId) -> Option<DocId> {
+        if self.exhausted {
+            return None;
+        }
+        if self.svs {
+            if self.read_ahead.is_none() {
+                self.read_ahead = Some(self.read_ahead());
+            }
+            let read_ahead = self.read_ahead.as_mut().unwrap();
+            while read_ahead.next < read_ahead.docs.len() && read_ahead.docs[read_ahead.next] < min {
+                read_ahead.next += 1;
+            }
+            if read_ahead.next == read_ahead.docs.len() {
+                self.exhausted = true;
+                return None;
+            }
+            self.current = read_ahead.docs[read_ahead.next];
+            read_ahead.next += 1;
+            self.matches += 1;
+            return Some(self.current);
+        }
+        // All cursors are on the last match, or not started yet
+        let mut target = min;
+        for cursor in &mut self.cursors {
+            match cursor.advance() {
+                Some(doc_id) => target = target.max(doc_id),
+                None => {
+                    self.exhausted = true;
+                    return None;
+                }
+            }
+        }
 
-impl<C: PostingsCursor> Intersect<C> {
-    pub fn new(cursors: Vec<C>) -> Self {
-        let size = cursors.iter().map(|c| c.remains()).min().unwrap();
-        Intersect {
-            cursors: cursors,
-            current: 0,
-            size: size,
+        'align: loop {
+            for k in 0..self.probes.len() {
+                let i = self.probes[k];
+                match self.seek(i, target) {
+                    Some(doc_id) if doc_id > target => {
+                        target = doc_id;
+                        continue 'align;
+                    }
+                    Some(_) => {}
+                    None => {
+                        self.exhausted = true;
+                        return None;
+                    }
+                }
+            }
+            self.current = target;
+            self.matches += 1;
+            return Some(target);
         }
     }
 }
@@ -80,36 +195,22 @@ impl<C: PostingsCursor> PostingsCursor for Intersect<C> {
     type PS = C::PS;
 
     unsafe fn current(&self) -> DocId {
-        // when matched then all cursors must have the same current() docid
-        assert_eq!(self.cursors[0].current(), self.current);
+        // when matched then all cursors must have the same current() docid, unless read ahead
+        if !self.svs {
+            assert_eq!(self.cursors[0].current(), self.current);
+        }
         self.current
     }
 
     fn advance(&mut self) -> Option<DocId> {
-        for cur in &mut self.cursors {
-            if let Some(doc_id) = cur.advance() {
-                // Start next iteration alignment with maximum doc id
-                if doc_id > self.current {
-                    self.current = doc_id;
-                }
-            } else {
-                return None;
-            }
-        }
+        self.next_match(0)
+    }
 
-        'align: loop {
-            for cur in &mut self.cursors {
-                if let Some(next_doc) = cur.advance_to(self.current) {
-                    if next_doc > self.current {
-                        self.current = next_doc;
-                        continue 'align;
-                    }
-                } else {
-                    return None;
-                }
-            }
-            return Some(self.current);
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        if self.matches > 0 && !self.exhausted && self.current == doc_id {
+            return Some(doc_id);
         }
+        self.next_match(doc_id)
     }
 
     fn remains(&self) -> usize {
@@ -118,9 +219,137 @@ impl<C: PostingsCursor> PostingsCursor for Intersect<C> {
 
     fn catch_up(&mut self, result: &mut VecPostings) -> usize {
         let mut result_size = 0;
+        if let Some(ref mut read_ahead) = self.read_ahead {
+            for i in 0..self.cursors.len() {
+                let starts = &read_ahead.starts[i];
+                let mut k = read_ahead.next_starts[i];
+                while starts[k].0 < self.current {
+                    k += 1;
+                }
+                let postings = &read_ahead.postings[i];
+                let (docs, positions) = match starts.get(k + 1) {
+                    Some(&(_, end, end_position)) => (starts[k].1..end, starts[k].2..end_position),
+                    None => (starts[k].1..postings.docs.len(), starts[k].2..postings.positions.len()),
+                };
+                result_size += docs.len();
+                result.docs.extend_from_slice(&postings.docs[docs.clone()]);
+                result.tfs.extend_from_slice(&postings.tfs[docs]);
+                result.positions.extend_from_slice(&postings.positions[positions]);
+                read_ahead.next_starts[i] = k + 1;
+            }
+            return result_size;
+        }
         for cur in &mut self.cursors {
             result_size += cur.catch_up(result);
         }
         result_size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::XorShift;
+    use postings::{Postings,RawCursor,SequenceStorage,SliceSequence};
+
+    fn random_docs(rng: &mut XorShift, max_len: u64, max_doc: u64) -> Vec<DocId> {
+        let mut docs = (0..rng.below(max_len + 1)).map(|_| rng.range(1, max_doc)).collect::<Vec<_>>();
+        docs.sort();
+        docs.dedup();
+        docs
+    }
+
+    /// Cursor over docs with a tf of one and the doc itself for a position
+    fn cursor(p: &Postings<Vec<DocId>, Vec<DocId>, Vec<DocId>>) -> RawCursor<SliceSequence<'_>, SliceSequence<'_>, SliceSequence<'_>> {
+        RawCursor::new(Postings {
+            docs: (&p.docs).to_sequence(),
+            tfs: (&p.tfs).to_sequence(),
+            positions: (&p.positions).to_sequence(),
+        })
+    }
+
+    fn postings(docs: Vec<DocId>) -> Postings<Vec<DocId>, Vec<DocId>, Vec<DocId>> {
+        Postings {
+            tfs: (0..docs.len() as DocId + 1).collect(),
+            positions: docs.clone(),
+            docs,
+        }
+    }
+
+    fn naive(lists: &[Vec<DocId>]) -> Vec<DocId> {
+        match lists.split_first() {
+            Some((first, rest)) => first.iter().cloned().filter(|d| rest.iter().all(|l| l.contains(d))).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_intersect_random() {
+        let mut rng = XorShift::new(39);
+        for round in 0..500 {
+            let lists = (0..rng.below(5)).map(|_| {
+                // Mix short and long lists to exercise both ways of probing
+                let max_len = if rng.chance(0.3) { 200 } else { 10 };
+                random_docs(&mut rng, max_len, 300)
+            }).collect::<Vec<_>>();
+            let expected = naive(&lists);
+            let ps = lists.iter().cloned().map(postings).collect::<Vec<_>>();
+
+            for &svs in &[false, true] {
+                let result = Intersect::with_svs(ps.iter().map(cursor).collect(), svs).collect();
+                assert_eq!(result.docs, expected.iter().flat_map(|&d| vec![d; lists.len()]).collect::<Vec<_>>(), "round {}, svs {}", round, svs);
+                assert_eq!(result.positions, result.docs);
+
+                // Skipping to random targets finds the first match not below them
+                let mut intersect = Intersect::with_svs(ps.iter().map(cursor).collect(), svs);
+                let (mut target, mut last) = (0, None);
+                loop {
+                    target += rng.below(40);
+                    let first = if last == Some(target) { last } else { expected.iter().cloned().find(|&d| d >= target) };
+                    let found = intersect.advance_to(target);
+                    assert_eq!(found, first, "round {}, svs {}, target {}", round, svs, target);
+                    match found {
+                        Some(doc_id) => target = doc_id,
+                        None => break,
+                    }
+                    last = found;
+                }
+                assert!(intersect.advance().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_intersect_edge_cases() {
+        let mut none = Intersect::<RawCursor<SliceSequence, SliceSequence, SliceSequence>>::new(Vec::new());
+        assert_eq!(none.remains(), 0);
+        assert!(none.advance().is_none());
+        assert!(none.advance_to(5).is_none());
+
+        let ps = postings(vec![2, 4, 8]);
+        let mut one = Intersect::new(vec![cursor(&ps)]);
+        assert_eq!(one.advance_to(3), Some(4));
+        assert_eq!(one.advance_to(4), Some(4));
+        assert_eq!(one.advance(), Some(8));
+        assert!(one.advance().is_none());
+        assert!(one.advance().is_none());
+
+        let empty = postings(Vec::new());
+        assert!(Intersect::new(vec![cursor(&ps), cursor(&empty)]).advance().is_none());
+        assert!(Intersect::with_svs(vec![cursor(&ps), cursor(&empty), cursor(&ps)], true).advance().is_none());
+    }
+
+    #[test]
+    fn test_svs_choice() {
+        let short = postings(vec![64]);
+        let long = postings((1..=64).collect());
+        let medium = postings((1..=8).map(|d| d * 8).collect());
+        assert!(Intersect::new(vec![cursor(&long), cursor(&short), cursor(&long)]).svs);
+        assert!(!Intersect::new(vec![cursor(&short), cursor(&long)]).svs);
+        assert!(!Intersect::new(vec![cursor(&long), cursor(&short), cursor(&medium)]).svs);
+
+        let mut svs = Intersect::new(vec![cursor(&long), cursor(&short), cursor(&medium)]);
+        svs.svs = true;
+        assert_eq!(svs.collect().docs, vec![64; 3]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::mem;
+use types::*;
+use postings::{VecPostings,PostingsCursor};
+
+/// Postings of a single matched document. Every cursor at the leaves of the cursor tree
+/// contributes a doc, a tf and its positions, in order of the cursors
+pub struct DocMatch {
+    pub doc_id: DocId,
+    pub postings: VecPostings,
+}
+
+/// Lazy iterator over matched documents of a cursor. Nothing is materialized beyond the
+/// current document
+pub struct Matches<C: PostingsCursor> {
+    cursor: C,
+    buffer: VecPostings,
+}
+
+impl<C: PostingsCursor> Matches<C> {
+    pub fn new(cursor: C) -> Self {
+        Matches {
+            cursor,
+            buffer: VecPostings {
+                docs: Vec::new(),
+                tfs: Vec::new(),
+                positions: Vec::new(),
+            },
+        }
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cursor
+    }
+}
+
+impl<C: PostingsCursor> Iterator for Matches<C> {
+    type Item = DocMatch;
+
+    fn next(&mut self) -> Option<DocMatch> {
+        let doc_id = tryopt!(self.cursor.advance());
+        let _ = self.cursor.catch_up(&mut self.buffer);
+        let empty = VecPostings {
+            docs: Vec::with_capacity(self.buffer.docs.len()),
+            tfs: Vec::with_capacity(self.buffer.tfs.len()),
+            positions: Vec::with_capacity(self.buffer.positions.len()),
+        };
+        Some(DocMatch {
+            doc_id,
+            postings: mem::replace(&mut self.buffer, empty),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cursor.remains()))
+    }
+
+    /// Counts matches without copying their tfs and positions
+    fn count(self) -> usize {
+        DocIds::new(self.cursor).count()
+    }
+}
+
+/// Lazy iterator over DocIds of matched documents. Their tfs and positions are skipped
+pub struct DocIds<C: PostingsCursor> {
+    cursor: C,
+}
+
+impl<C: PostingsCursor> DocIds<C> {
+    pub fn new(cursor: C) -> Self {
+        DocIds {
+            cursor,
+        }
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cursor
+    }
+}
+
+impl<C: PostingsCursor> Iterator for DocIds<C> {
+    type Item = DocId;
+
+    fn next(&mut self) -> Option<DocId> {
+        self.cursor.advance()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cursor.remains()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postings::{RawCursor,Intersect,Postings,SequenceStorage};
+
+    #[test]
+    fn test_matches() {
+        let ps1 = VecPostings {
+            docs: vec![1, 2, 5],
+            tfs: vec![0, 1, 3, 4],
+            positions: vec![1, 2, 3, 4],
+        };
+        let ps2 = VecPostings {
+            docs: vec![2, 3, 5],
+            tfs: vec![0, 1, 2, 4],
+            positions: vec![7, 8, 9, 10],
+        };
+        let cursors = || vec![&ps1, &ps2].into_iter().map(|ps| RawCursor::new(Postings {
+            docs: (&ps.docs).to_sequence(),
+            tfs: (&ps.tfs).to_sequence(),
+            positions: (&ps.positions).to_sequence(),
+        })).collect::<Vec<_>>();
+
+        let matches = Matches::new(Intersect::new(cursors())).collect::<Vec<_>>();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].doc_id, 2);
+        assert_eq!(matches[0].postings.docs, vec![2, 2]);
+        assert_eq!(matches[0].postings.tfs, vec![2, 1]);
+        assert_eq!(matches[0].postings.positions, vec![2, 3, 7]);
+        assert_eq!(matches[1].postings.tfs, vec![1, 2]);
+        assert_eq!(matches[1].postings.positions, vec![4, 9, 10]);
+
+        assert_eq!(Matches::new(Intersect::new(cursors())).count(), 2);
+        assert_eq!(DocIds::new(Intersect::new(cursors())).collect::<Vec<_>>(), vec![2, 5]);
+    }
+}
@@ -3,37 +3,49 @@ use std::iter::FromIterator; // needed for ::from_iter
 use std::collections::BinaryHeap;
 use postings::PostingsCursor;
 
-pub struct FrontierPointer<C: PostingsCursor> {
+/// Sorted source taking part in a k-way merge through the frontier heap
+pub trait Frontier {
+    /// Order of the current items of two sources
+    fn cmp_current(&self, other: &Self) -> Ordering;
+}
+
+impl<C: PostingsCursor> Frontier for C {
+    fn cmp_current(&self, other: &Self) -> Ordering {
+        unsafe {self.current().cmp(&other.current())}
+    }
+}
+
+pub struct FrontierPointer<C: Frontier> {
     pub cursor: C,
 }
 
-impl<C: PostingsCursor> Ord for FrontierPointer<C> {
+impl<C: Frontier> Ord for FrontierPointer<C> {
     fn cmp(&self, other: &Self) -> Ordering {
         // Switch compare order because Rust's BinaryHeap is a maxheap We want a minheap
-        unsafe {self.cursor.current().cmp(&other.cursor.current()).reverse()}
+        self.cursor.cmp_current(&other.cursor).reverse()
     }
 }
 
-impl<C: PostingsCursor> PartialOrd for FrontierPointer<C> {
+impl<C: Frontier> PartialOrd for FrontierPointer<C> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<C: PostingsCursor> PartialEq for FrontierPointer<C> {
+impl<C: Frontier> PartialEq for FrontierPointer<C> {
     fn eq(&self, other: &Self) -> bool {
-        unsafe {self.cursor.current() == other.cursor.current()}
+        self.cursor.cmp_current(&other.cursor) == Ordering::Equal
     }
 }
 
-impl<C: PostingsCursor> Eq for FrontierPointer<C> {}
+impl<C: Frontier> Eq for FrontierPointer<C> {}
 
 
+/// Heap of advanced cursors. Depleted cursors are left out
 pub fn create_heap<C: PostingsCursor>(to_merge: Vec<C>) -> BinaryHeap<FrontierPointer<C>> {
-    BinaryHeap::from_iter(to_merge.into_iter().map(|mut cur| {
-        let _ = cur.advance().unwrap();
-        FrontierPointer {
+    BinaryHeap::from_iter(to_merge.into_iter().filter_map(|mut cur| {
+        cur.advance().map(|_| FrontierPointer {
             cursor: cur
-        }
+        })
     }))
 }
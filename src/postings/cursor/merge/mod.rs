@@ -1,6 +1,7 @@
 pub use self::without_duplicates::*;
 pub use self::with_duplicates::*;
+pub use self::frontier::{Frontier,FrontierPointer};
 pub mod without_duplicates;
 pub mod with_duplicates;
 
-mod frontier;
+pub mod frontier;
@@ -15,7 +15,7 @@ impl<C: PostingsCursor> MergeUnrolled<C> {
     }
 
     pub fn collect(&mut self) -> VecPostings {
-        let mut frontier = create_heap(self.to_merge.take().unwrap());
+        let mut frontier = create_heap(self.to_merge.take().unwrap_or_default());
         let mut result = VecPostings {
             docs: Vec::new(),
             tfs: Vec::new(),
@@ -24,7 +24,7 @@ impl<C: PostingsCursor> MergeUnrolled<C> {
 
         while let Some(mut cursor) = frontier.pop() {
             let _ = cursor.cursor.catch_up(&mut result);
-            if let Some(_) = cursor.cursor.advance() {
+            if cursor.cursor.advance().is_some() {
                 frontier.push(cursor);
             }
         }
@@ -41,12 +41,12 @@ pub struct Merge<C: PostingsCursor> {
 
 impl<C: PostingsCursor> Merge<C> {
     pub fn new(to_merge: Vec<C>) -> Self {
-        let size = to_merge.iter().map(|c| c.remains()).fold(0, |acc, x| acc + x);
+        let size = to_merge.iter().map(|c| c.remains()).sum();
         let heap = create_heap(to_merge);
         Merge{
             current_ptr: None,
             frontier: heap,
-            size: size,
+            size,
         }
     }
 }
@@ -70,33 +70,36 @@ impl<C: PostingsCursor> PostingsCursor for Merge<C> {
     }
 
     fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
-        let current_doc = unsafe {self.current()};
-        if current_doc >= doc_id {
-            Some(current_doc)
-        } else {
-            let mut ptrs = self.frontier.drain().collect::<Vec<_>>();
-            self.frontier.extend(ptrs.into_iter()
-                                     .map(|mut ptr| {
-                                         ptr.cursor.advance_to(doc_id);
-                                         ptr
-                                     }));
-            self.advance()
+        if let Some(ref ptr) = self.current_ptr {
+            if unsafe {ptr.cursor.current()} == doc_id {
+                return Some(doc_id);
+            }
+        }
+        if let Some(mut ptr) = self.current_ptr.take() {
+            if ptr.cursor.advance().is_some() {
+                self.frontier.push(ptr);
+            }
         }
+        // Only cursors behind the target may move, the others are already on a candidate
+        let ptrs = self.frontier.drain().collect::<Vec<_>>();
+        self.frontier.extend(ptrs.into_iter().filter_map(|mut ptr| {
+            if unsafe {ptr.cursor.current()} < doc_id {
+                ptr.cursor.advance_to(doc_id)?;
+            }
+            Some(ptr)
+        }));
+        self.advance()
     }
 
     fn advance(&mut self) -> Option<DocId> {
         if let Some(mut ptr) = self.current_ptr.take() {
-            if let Some(_) = ptr.cursor.advance() {
+            if ptr.cursor.advance().is_some() {
                 self.frontier.push(ptr);
             }
-        } 
-        
-        self.current_ptr = self.frontier.pop();
-        if let Some(ref ptr) = self.current_ptr {
-            unsafe {Some(ptr.cursor.current())}
-        } else {
-            None
         }
+
+        self.current_ptr = self.frontier.pop();
+        self.current_ptr.as_ref().map(|ptr| unsafe {ptr.cursor.current()})
     }
 }
 
@@ -5,9 +5,9 @@ use super::frontier::{FrontierPointer,create_heap};
 
 fn keep_unique<T: Copy + PartialEq>(xs: &[T]) -> Vec<T> {
     let mut res = Vec::new();
-    if xs.len() > 0 {
+    if !xs.is_empty() {
         let mut group_elem = xs[0];
-        for x in xs[1..].into_iter().cloned() {
+        for x in xs[1..].iter().cloned() {
             if x != group_elem {
                 res.push(group_elem);
                 group_elem = x;
@@ -44,8 +44,11 @@ impl<C: PostingsCursor> MergerWithoutDuplicatesUnrolled<C> {
        //}
        //println!("---");
 
-        let mut frontier = create_heap(self.to_merge.take().unwrap());
-        let mut ptr = frontier.pop().unwrap();
+        let mut frontier = create_heap(self.to_merge.take().unwrap_or_default());
+        let mut ptr = match frontier.pop() {
+            Some(ptr) => ptr,
+            None => return res,
+        };
         let mut current_doc = unsafe {ptr.cursor.current()};
 
         let mut merged = VecPostings {
@@ -56,8 +59,8 @@ impl<C: PostingsCursor> MergerWithoutDuplicatesUnrolled<C> {
 
         macro_rules! ADD {
             () => {
-                assert!(merged.positions.len() > 0);
-                (&mut merged.positions[..]).sort();
+                assert!(!merged.positions.is_empty());
+                merged.positions[..].sort();
                 let unique_positions = keep_unique(&merged.positions);
                 let tf = unique_positions.len() as DocId;
 
@@ -76,7 +79,7 @@ impl<C: PostingsCursor> MergerWithoutDuplicatesUnrolled<C> {
                 let next_doc = unsafe {ptr.cursor.current()};
                 if next_doc == current_doc {
                     let _ = ptr.cursor.catch_up(&mut merged);
-                    if let Some(_) = ptr.cursor.advance() {
+                    if ptr.cursor.advance().is_some() {
                         frontier.push(ptr);
                     }
                 } else {
@@ -102,9 +105,13 @@ impl<C: PostingsCursor> MergerWithoutDuplicatesUnrolled<C> {
     }
 }
 
+/// Union of cursors. Documents present in several cursors come out once with their positions
+/// merged. Cursors are caught up only when the union is, so documents can be counted without
+/// ever reading their tfs and positions
 pub struct MergerWithoutDuplicates<C: PostingsCursor> {
     frontier: BinaryHeap<FrontierPointer<C>>,
-    current_ptr: Option<FrontierPointer<C>>,
+    /// Cursors positioned on the current document
+    current: Vec<FrontierPointer<C>>,
     current_doc: DocId,
     merged: VecPostings,
     size: usize,
@@ -113,24 +120,40 @@ pub struct MergerWithoutDuplicates<C: PostingsCursor> {
 
 impl<C: PostingsCursor> MergerWithoutDuplicates<C> {
     pub fn new(to_merge: Vec<C>) -> Self {
-        let size = to_merge.iter().map(|c| c.remains()).fold(0, |acc, x| acc + x);
-
-        let mut heap = create_heap(to_merge);
-        let first_ptr = heap.pop().unwrap();
-        let first_doc = unsafe {first_ptr.cursor.current()};
-
+        let size = to_merge.iter().map(|c| c.remains()).sum();
         MergerWithoutDuplicates {
-            frontier: heap,
-            current_ptr: Some(first_ptr),
-            current_doc: first_doc,
+            frontier: create_heap(to_merge),
+            current: Vec::new(),
+            current_doc: 0,
             merged: VecPostings {
                 docs: Vec::new(),
                 tfs: Vec::new(),
                 positions: Vec::new(),
             },
-            size: size,
-            processed: 1, // heap already popped
+            size,
+            processed: 0,
+        }
+    }
+
+    /// Move cursors off the current document back to the frontier
+    fn leave_current(&mut self) {
+        for mut ptr in self.current.drain(..) {
+            if ptr.cursor.advance().is_some() {
+                self.frontier.push(ptr);
+            }
+        }
+    }
+
+    /// Take all cursors on the smallest document off the frontier
+    fn gather(&mut self) -> Option<DocId> {
+        let ptr = tryopt!(self.frontier.pop());
+        self.current_doc = unsafe {ptr.cursor.current()};
+        self.current.push(ptr);
+        while self.frontier.peek().is_some_and(|next| unsafe {next.cursor.current()} == self.current_doc) {
+            self.current.push(self.frontier.pop().unwrap());
         }
+        self.processed += self.current.len();
+        Some(self.current_doc)
     }
 }
 
@@ -140,62 +163,47 @@ impl<C: PostingsCursor> PostingsCursor for MergerWithoutDuplicates<C> {
     type PS = C::PS;
 
     unsafe fn current(&self) -> DocId {
-        self.current_ptr.as_ref().unwrap().cursor.current()
+        assert!(!self.current.is_empty());
+        self.current_doc
     }
 
     fn advance(&mut self) -> Option<DocId> {
-        if self.current_ptr.is_none() {
-            return None;
+        self.leave_current();
+        self.gather()
+    }
+
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        if !self.current.is_empty() && self.current_doc == doc_id {
+            return Some(doc_id);
         }
+        self.leave_current();
+        // Only cursors behind the target may move, the others are already on a candidate
+        let ptrs = self.frontier.drain().collect::<Vec<_>>();
+        self.frontier.extend(ptrs.into_iter().filter_map(|mut ptr| {
+            if unsafe {ptr.cursor.current()} < doc_id {
+                ptr.cursor.advance_to(doc_id)?;
+            }
+            Some(ptr)
+        }));
+        self.gather()
+    }
+
+    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
         self.merged.docs.clear();
         self.merged.tfs.clear();
         self.merged.positions.clear();
-
-        let mut ptr = self.current_ptr.take().unwrap();
-        let current_doc = self.current_doc;
-
-        loop {
-            //println!("LOOPING");
-            let next_doc = unsafe {ptr.cursor.current()};
-            if next_doc == current_doc {
-                self.processed += 1;
-                let _ = ptr.cursor.catch_up(&mut self.merged);
-
-                if let Some(_) = ptr.cursor.advance() {
-                    //println!("putting back: {}", next_doc);
-                    self.frontier.push(ptr);
-                }
-            } else {
-                self.current_doc = next_doc;
-                    //println!("putting backa: {}", self.current_doc);
-                self.current_ptr = Some(ptr);
-                // self.frontier.push(ptr);
-                //println!("HEAP SIZE: {}", self.frontier.len());
-                break;
-            }
-
-            if let Some(next_ptr) = self.frontier.pop() {
-                ptr = next_ptr;
-            } else {
-                self.current_ptr = None;
-                break;
-            }
+        for ptr in &mut self.current {
+            let _ = ptr.cursor.catch_up(&mut self.merged);
         }
-        //println!("MIMO LOOP");
-
-        self.merged.docs.push(current_doc);
-        Some(current_doc)
-    }
 
-    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
-        assert!(self.merged.positions.len() > 0, "No positions found. Is 'tfs' encoded as cumulative?");
-        (&mut self.merged.positions[..]).sort();
+        assert!(!self.merged.positions.is_empty(), "No positions found. Is 'tfs' encoded as cumulative?");
+        self.merged.positions[..].sort();
         self.merged.positions = keep_unique(&self.merged.positions);
         let tf = self.merged.positions.len();
 
         result.positions.extend_from_slice(&self.merged.positions[..]);
         result.tfs.push(tf as DocId);
-        result.docs.push(self.merged.docs[0]);
+        result.docs.push(self.current_doc);
 
         1
     }
@@ -241,4 +249,46 @@ mod tests {
         assert_eq!(merged.tfs, vec![1, 2, 3]); // NOTE: result tfs are not cumulated though!
         assert_eq!(merged.positions, vec![1, 1, 2, 1, 2, 3]);
     }
+
+    /// Cursor that must never be caught up
+    struct DocsOnly<C: PostingsCursor>(C);
+
+    impl<C: PostingsCursor> PostingsCursor for DocsOnly<C> {
+        type DS = C::DS;
+        type TS = C::TS;
+        type PS = C::PS;
+
+        unsafe fn current(&self) -> DocId {
+            self.0.current()
+        }
+
+        fn remains(&self) -> usize {
+            self.0.remains()
+        }
+
+        fn advance(&mut self) -> Option<DocId> {
+            self.0.advance()
+        }
+
+        fn catch_up(&mut self, _result: &mut VecPostings) -> usize {
+            panic!("Positions must not be read");
+        }
+    }
+
+    #[test]
+    fn test_counting_does_not_catch_up() {
+        let ps = [
+            VecPostings { docs: vec![1, 4, 6], tfs: vec![0, 1, 2, 3], positions: vec![0, 0, 0] },
+            VecPostings { docs: vec![4, 5], tfs: vec![0, 1, 2], positions: vec![0, 0] },
+            VecPostings { docs: vec![], tfs: vec![0], positions: vec![] },
+        ];
+        let cursors = ps.iter().map(|p| DocsOnly(RawCursor::new(Postings {
+            docs: (&p.docs).to_sequence(),
+            tfs: (&p.tfs).to_sequence(),
+            positions: (&p.positions).to_sequence(),
+        }))).collect();
+
+        let merger = MergerWithoutDuplicates::new(cursors);
+        assert_eq!(merger.doc_ids().collect::<Vec<_>>(), vec![1, 4, 5, 6]);
+    }
 }
@@ -1,10 +1,21 @@
 pub use self::rawcursor::*;
 pub use self::intersect::*;
 pub use self::merge::*;
+pub use self::remap::*;
+pub use self::filter::*;
+pub use self::matches::*;
+pub use self::union::*;
 
 pub mod rawcursor;
 pub mod intersect;
 pub mod merge;
+pub mod remap;
+pub mod filter;
+pub mod matches;
+pub mod union;
+
+#[cfg(test)]
+mod proptests;
 
 use types::*;
 use postings::{VecPostings,Sequence};
@@ -15,6 +26,9 @@ pub trait PostingsCursor {
     type PS: Sequence;
 
     // TODO unsafe because it performs no bounds or error checking
+    /// # Safety
+    ///
+    /// The cursor must be positioned on a document, ie. `advance` must have returned `Some`.
     unsafe fn current(&self) -> DocId;
 
     fn remains(&self) -> usize;
@@ -34,13 +48,23 @@ pub trait PostingsCursor {
         None
     }
 
+    /// Iterate matched documents with their postings lazily
+    fn matches(self) -> Matches<Self> where Self: Sized {
+        Matches::new(self)
+    }
+
+    /// Iterate DocIds of matched documents lazily, without their postings
+    fn doc_ids(self) -> DocIds<Self> where Self: Sized {
+        DocIds::new(self)
+    }
+
     fn collect(&mut self) -> VecPostings {
         let mut result = VecPostings {
             docs: Vec::new(),
             tfs: Vec::new(),
             positions: Vec::new(),
         };
-        while let Some(_) = self.advance() {
+        while self.advance().is_some() {
             let _ = self.catch_up(&mut result);
         }
         result
@@ -0,0 +1,239 @@
+//! Generated postings run through every cursor and compared against a reference model of
+//! their results
+
+use types::*;
+use bitmap::Bitmap;
+use random::XorShift;
+use postings::{Postings,VecPostings,PostingsCursor,SequenceStorage,SliceSequence};
+use postings::{RawCursor,Intersect,Merge,MergeUnrolled,MergerWithoutDuplicates,MergerWithoutDuplicatesUnrolled,Remap,Filter};
+
+const ROUNDS: u64 = 300;
+
+/// A document with its absolute positions
+type Entry = (DocId, Vec<DocId>);
+
+type Raw<'a> = RawCursor<SliceSequence<'a>, SliceSequence<'a>, SliceSequence<'a>>;
+
+/// Ascending documents with 1 to 3 ascending positions each. Empty and single document lists
+/// come up often
+fn random_entries(rng: &mut XorShift, max_doc: u64) -> Vec<Entry> {
+    let len = match rng.below(5) {
+        0 => 0,
+        1 => 1,
+        2 => rng.below(max_doc),
+        _ => rng.below(10),
+    };
+    let mut docs = (0..len).map(|_| rng.below(max_doc)).collect::<Vec<_>>();
+    docs.sort();
+    docs.dedup();
+    docs.into_iter().map(|doc| {
+        let mut positions = (0..rng.range(1, 4)).map(|_| rng.below(20)).collect::<Vec<_>>();
+        positions.sort();
+        positions.dedup();
+        (doc, positions)
+    }).collect()
+}
+
+/// Postings with cumulative tfs, like in a segment
+fn to_postings(entries: &[Entry]) -> VecPostings {
+    let mut postings = VecPostings { docs: Vec::new(), tfs: vec![0], positions: Vec::new() };
+    for &(doc, ref positions) in entries {
+        postings.docs.push(doc);
+        postings.positions.extend_from_slice(positions);
+        postings.tfs.push(postings.positions.len() as DocId);
+    }
+    postings
+}
+
+fn raw(p: &VecPostings) -> Raw<'_> {
+    RawCursor::new(Postings {
+        docs: (&p.docs).to_sequence(),
+        tfs: (&p.tfs).to_sequence(),
+        positions: (&p.positions).to_sequence(),
+    })
+}
+
+/// Entries of collected postings, whose tfs are not cumulative
+fn to_entries(collected: &VecPostings) -> Vec<Entry> {
+    let mut start = 0;
+    collected.docs.iter().zip(&collected.tfs).map(|(&doc, &tf)| {
+        let positions = collected.positions[start..start + tf as usize].to_vec();
+        start += tf as usize;
+        (doc, positions)
+    }).collect()
+}
+
+fn model_merge(lists: &[Vec<Entry>]) -> Vec<Entry> {
+    let mut all = lists.concat();
+    all.sort();
+    all
+}
+
+fn model_union(lists: &[Vec<Entry>]) -> Vec<Entry> {
+    let mut union: Vec<Entry> = Vec::new();
+    for (doc, positions) in model_merge(lists) {
+        match union.last_mut() {
+            Some(last) if last.0 == doc => last.1.extend(positions),
+            _ => union.push((doc, positions)),
+        }
+    }
+    for entry in &mut union {
+        entry.1.sort();
+        entry.1.dedup();
+    }
+    union
+}
+
+/// Postings of a match come from every list in their order
+fn model_intersect(lists: &[Vec<Entry>]) -> Vec<Entry> {
+    let first = match lists.first() {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    let mut result = Vec::new();
+    for &(doc, _) in first {
+        let found = lists.iter().map(|l| l.iter().find(|e| e.0 == doc).cloned()).collect::<Option<Vec<_>>>();
+        if let Some(found) = found {
+            result.extend(found);
+        }
+    }
+    result
+}
+
+/// Check a cursor against expected entries: its postings, its DocIds alone, and a random walk
+/// of skips and advances. Entries of the same document may come in any order when
+/// `ties_unordered`. Unless `one_per_doc`, every entry is a separate step of the cursor
+fn check<C, F>(rng: &mut XorShift, make: F, expected: &[Entry], ties_unordered: bool, one_per_doc: bool, what: &str)
+    where C: PostingsCursor, F: Fn() -> C
+{
+    let mut steps = expected.iter().map(|e| e.0).collect::<Vec<_>>();
+    if one_per_doc {
+        steps.dedup();
+    }
+    assert!(make().remains() >= steps.len(), "{}: remains", what);
+
+    let mut entries = to_entries(&make().collect());
+    if ties_unordered {
+        entries.sort();
+    }
+    assert_eq!(entries, expected, "{}: postings", what);
+    assert_eq!(make().doc_ids().collect::<Vec<_>>(), steps, "{}: doc ids", what);
+
+    let mut cursor = make();
+    let mut position: Option<usize> = None;
+    let mut target = 0;
+    loop {
+        let (found, want) = if rng.chance(0.3) {
+            let want = position.map_or(0, |p| p + 1);
+            (cursor.advance(), want)
+        } else {
+            target += rng.below(10);
+            let want = match position {
+                Some(p) if steps[p] == target => p,
+                _ => {
+                    let from = position.map_or(0, |p| p + 1);
+                    from + steps[from.min(steps.len())..].iter().take_while(|&&d| d < target).count()
+                }
+            };
+            (cursor.advance_to(target), want)
+        };
+        assert_eq!(found, steps.get(want).cloned(), "{}: step after {:?}, target {}", what, position, target);
+        match found {
+            Some(doc_id) => target = doc_id,
+            None => break,
+        }
+        position = Some(want);
+    }
+    assert!(cursor.advance().is_none(), "{}: advance after the end", what);
+    assert!(cursor.advance_to(target + 1).is_none(), "{}: advance_to after the end", what);
+}
+
+#[test]
+fn test_raw_cursor() {
+    let mut rng = XorShift::new(1);
+    for round in 0..ROUNDS {
+        let entries = random_entries(&mut rng, 50);
+        let p = to_postings(&entries);
+        check(&mut rng, || raw(&p), &entries, false, false, &format!("raw, round {}", round));
+    }
+}
+
+#[test]
+fn test_merges() {
+    let mut rng = XorShift::new(2);
+    for round in 0..ROUNDS {
+        let lists = (0..rng.below(5)).map(|_| random_entries(&mut rng, 50)).collect::<Vec<_>>();
+        let ps = lists.iter().map(|l| to_postings(l)).collect::<Vec<_>>();
+        let raws = || ps.iter().map(raw).collect::<Vec<_>>();
+
+        let merged = model_merge(&lists);
+        check(&mut rng, || Merge::new(raws()), &merged, true, false, &format!("merge, round {}", round));
+        let mut entries = to_entries(&MergeUnrolled::new(raws()).collect());
+        entries.sort();
+        assert_eq!(entries, merged, "merge unrolled, round {}", round);
+
+        let union = model_union(&lists);
+        check(&mut rng, || MergerWithoutDuplicates::new(raws()), &union, false, false, &format!("union, round {}", round));
+        assert_eq!(to_entries(&MergerWithoutDuplicatesUnrolled::new(raws()).collect()), union,
+                   "union unrolled, round {}", round);
+    }
+}
+
+#[test]
+fn test_intersect() {
+    let mut rng = XorShift::new(3);
+    for round in 0..ROUNDS {
+        let lists = (0..rng.below(4)).map(|_| random_entries(&mut rng, 30)).collect::<Vec<_>>();
+        let ps = lists.iter().map(|l| to_postings(l)).collect::<Vec<_>>();
+        check(&mut rng, || Intersect::new(ps.iter().map(raw).collect()), &model_intersect(&lists), false, true,
+              &format!("intersect, round {}", round));
+        check(&mut rng, || Intersect::with_svs(ps.iter().map(raw).collect(), true), &model_intersect(&lists), false, true,
+              &format!("intersect set versus set, round {}", round));
+    }
+}
+
+#[test]
+fn test_remap_and_filter() {
+    let mut rng = XorShift::new(4);
+    for round in 0..ROUNDS {
+        let entries = random_entries(&mut rng, 50);
+        let p = to_postings(&entries);
+
+        let offset = rng.below(100);
+        let shifted = entries.iter().map(|&(doc, ref positions)| (doc + offset, positions.clone())).collect::<Vec<_>>();
+        check(&mut rng, || Remap::new(raw(&p), offset), &shifted, false, false, &format!("remap, round {}", round));
+
+        let mut deleted = Bitmap::new();
+        for _ in 0..rng.below(20) {
+            deleted.insert(rng.below(50));
+        }
+        let live = entries.iter().filter(|e| !deleted.contains(e.0)).cloned().collect::<Vec<_>>();
+        check(&mut rng, || Filter::new(raw(&p), &deleted), &live, false, false, &format!("filter, round {}", round));
+    }
+}
+
+#[test]
+fn test_combinations() {
+    let mut rng = XorShift::new(5);
+    for round in 0..ROUNDS {
+        // Prefix queries intersect unions of the terms under each prefix
+        let groups = (0..rng.range(1, 4)).map(|_| {
+            (0..rng.range(1, 4)).map(|_| random_entries(&mut rng, 30)).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        let ps = groups.iter().map(|g| g.iter().map(|l| to_postings(l)).collect::<Vec<_>>()).collect::<Vec<_>>();
+        let unions = groups.iter().map(|g| model_union(g)).collect::<Vec<_>>();
+        let make = || Intersect::new(ps.iter().map(|g| MergerWithoutDuplicates::new(g.iter().map(raw).collect())).collect());
+        check(&mut rng, make, &model_intersect(&unions), false, true, &format!("intersect of unions, round {}", round));
+        let make = || Intersect::with_svs(ps.iter().map(|g| MergerWithoutDuplicates::new(g.iter().map(raw).collect())).collect(), true);
+        check(&mut rng, make, &model_intersect(&unions), false, true, &format!("intersect of unions set versus set, round {}", round));
+
+        // Results of segments merged together
+        let lists = (0..rng.range(1, 4)).map(|_| random_entries(&mut rng, 30)).collect::<Vec<_>>();
+        let ps = lists.iter().map(|l| to_postings(l)).collect::<Vec<_>>();
+        let make = || Merge::new(ps.iter().enumerate().map(|(i, p)| Remap::new(raw(p), 100 * i as DocId)).collect());
+        let shifted = lists.iter().enumerate().map(|(i, l)| {
+            l.iter().map(|&(doc, ref positions)| (doc + 100 * i as DocId, positions.clone())).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        check(&mut rng, make, &model_merge(&shifted), false, false, &format!("merge of remaps, round {}", round));
+    }
+}
@@ -11,7 +11,7 @@ impl<DS: Sequence, TS: Sequence, PS: Sequence> RawCursor<DS, TS, PS> {
         // prime tfs, because it has one more element at the end
         postings.tfs.next();
         RawCursor {
-            postings: postings,
+            postings,
             ahead: 0,
         }
     }
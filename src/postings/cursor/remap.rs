@@ -0,0 +1,124 @@
+use types::*;
+use postings::{VecPostings,PostingsCursor};
+
+/// Maps DocIds of the wrapped cursor into another segment's DocId space. DocIds are shifted by
+/// a constant offset, after an optional lookup in a table of new DocIds. The table must be
+/// ascending, eg. DocIds of a segment compacted by dropping its deleted documents
+pub struct Remap<'a, C: PostingsCursor> {
+    cursor: C,
+    offset: DocId,
+    table: Option<&'a [DocId]>,
+}
+
+impl<'a, C: PostingsCursor> Remap<'a, C> {
+    pub fn new(cursor: C, offset: DocId) -> Self {
+        Remap {
+            cursor,
+            offset,
+            table: None,
+        }
+    }
+
+    /// DocId `d` of the cursor becomes `offset + table[d]`
+    pub fn with_table(cursor: C, offset: DocId, table: &'a [DocId]) -> Self {
+        Remap {
+            cursor,
+            offset,
+            table: Some(table),
+        }
+    }
+
+    fn map(&self, doc_id: DocId) -> DocId {
+        match self.table {
+            Some(table) => self.offset + table[doc_id as usize],
+            None => self.offset + doc_id,
+        }
+    }
+}
+
+impl<'a, C: PostingsCursor> PostingsCursor for Remap<'a, C> {
+    type DS = C::DS;
+    type TS = C::TS;
+    type PS = C::PS;
+
+    unsafe fn current(&self) -> DocId {
+        self.map(self.cursor.current())
+    }
+
+    fn remains(&self) -> usize {
+        self.cursor.remains()
+    }
+
+    fn advance(&mut self) -> Option<DocId> {
+        self.cursor.advance().map(|doc_id| self.map(doc_id))
+    }
+
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        let target = doc_id.saturating_sub(self.offset);
+        let target = match self.table {
+            // First DocId mapped at or behind the target
+            Some(table) => table.partition_point(|&new_doc| new_doc < target) as DocId,
+            None => target,
+        };
+        self.cursor.advance_to(target).map(|doc_id| self.map(doc_id))
+    }
+
+    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
+        let n = self.cursor.catch_up(result);
+        let len = result.docs.len();
+        for i in len - n..len {
+            result.docs[i] = self.map(result.docs[i]);
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postings::{RawCursor,Postings,SequenceStorage};
+
+    #[test]
+    fn test_remap() {
+        let ps = VecPostings {
+            docs: vec![1, 3, 8],
+            tfs: vec![0, 1, 3, 4],
+            positions: vec![5, 1, 2, 7],
+        };
+        let cursor = RawCursor::new(Postings {
+            docs: (&ps.docs).to_sequence(),
+            tfs: (&ps.tfs).to_sequence(),
+            positions: (&ps.positions).to_sequence(),
+        });
+
+        let mut remap = Remap::new(cursor, 10);
+        assert_eq!(remap.advance(), Some(11));
+        assert_eq!(remap.advance_to(12), Some(13));
+        let merged = remap.collect();
+        assert_eq!(merged.docs, vec![18]);
+        assert_eq!(merged.tfs, vec![1]);
+        assert_eq!(merged.positions, vec![7]);
+    }
+
+    #[test]
+    fn test_remap_with_table() {
+        let ps = VecPostings {
+            docs: vec![1, 3, 5, 8],
+            tfs: vec![0, 1, 2, 3, 4],
+            positions: vec![1, 3, 5, 8],
+        };
+        let cursor = RawCursor::new(Postings {
+            docs: (&ps.docs).to_sequence(),
+            tfs: (&ps.tfs).to_sequence(),
+            positions: (&ps.positions).to_sequence(),
+        });
+
+        // Documents 2, 4 and 6 are gone
+        let table = [0, 1, 1, 2, 2, 3, 3, 4, 5];
+        let mut remap = Remap::with_table(cursor, 10, &table);
+        assert_eq!(remap.advance(), Some(11));
+        assert_eq!(remap.advance_to(13), Some(13));
+        let rest = remap.collect();
+        assert_eq!(rest.docs, vec![15]);
+    }
+}
@@ -0,0 +1,117 @@
+use types::*;
+use postings::{VecPostings,PostingsCursor};
+use super::MergerWithoutDuplicates;
+
+/// Cursor handing out absolute positions of a cursor over delta encoded ones
+pub struct AbsolutePositions<C: PostingsCursor> {
+    cursor: C,
+}
+
+impl<C: PostingsCursor> PostingsCursor for AbsolutePositions<C> {
+    type DS = C::DS;
+    type TS = C::TS;
+    type PS = C::PS;
+
+    unsafe fn current(&self) -> DocId {
+        self.cursor.current()
+    }
+
+    fn remains(&self) -> usize {
+        self.cursor.remains()
+    }
+
+    fn advance(&mut self) -> Option<DocId> {
+        self.cursor.advance()
+    }
+
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        self.cursor.advance_to(doc_id)
+    }
+
+    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
+        let start = result.positions.len();
+        let n = self.cursor.catch_up(result);
+        for i in start + 1..result.positions.len() {
+            result.positions[i] += result.positions[i - 1];
+        }
+        n
+    }
+}
+
+/// Union of cursors over stored postings, whose positions are delta encoded within each
+/// document. Like `MergerWithoutDuplicates`, but positions are decoded before merging and
+/// encoded again, so the union reads as if its postings were stored
+pub struct Union<C: PostingsCursor> {
+    merger: MergerWithoutDuplicates<AbsolutePositions<C>>,
+}
+
+impl<C: PostingsCursor> Union<C> {
+    pub fn new(to_merge: Vec<C>) -> Self {
+        Union {
+            merger: MergerWithoutDuplicates::new(to_merge.into_iter().map(|cursor| AbsolutePositions { cursor }).collect()),
+        }
+    }
+}
+
+impl<C: PostingsCursor> PostingsCursor for Union<C> {
+    type DS = C::DS;
+    type TS = C::TS;
+    type PS = C::PS;
+
+    unsafe fn current(&self) -> DocId {
+        self.merger.current()
+    }
+
+    fn remains(&self) -> usize {
+        self.merger.remains()
+    }
+
+    fn advance(&mut self) -> Option<DocId> {
+        self.merger.advance()
+    }
+
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        self.merger.advance_to(doc_id)
+    }
+
+    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
+        let start = result.positions.len();
+        let n = self.merger.catch_up(result);
+        for i in (start + 1..result.positions.len()).rev() {
+            result.positions[i] -= result.positions[i - 1];
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postings::{RawCursor,Postings,SequenceStorage,SliceSequence};
+
+    fn cursor(ps: &VecPostings) -> RawCursor<SliceSequence<'_>, SliceSequence<'_>, SliceSequence<'_>> {
+        RawCursor::new(Postings {
+            docs: (&ps.docs).to_sequence(),
+            tfs: (&ps.tfs).to_sequence(),
+            positions: (&ps.positions).to_sequence(),
+        })
+    }
+
+    #[test]
+    fn test_union_of_delta_encoded_positions() {
+        // Positions 1 5 and 2 5 9 in document 2
+        let ps1 = VecPostings { docs: vec![1, 2], tfs: vec![0, 1, 3], positions: vec![7, 1, 4] };
+        let ps2 = VecPostings { docs: vec![2, 4], tfs: vec![0, 3, 4], positions: vec![2, 3, 4, 0] };
+
+        let mut union = Union::new(vec![cursor(&ps1), cursor(&ps2)]);
+        assert_eq!(union.remains(), 4);
+        let merged = union.collect();
+        assert_eq!(merged.docs, vec![1, 2, 4]);
+        assert_eq!(merged.tfs, vec![1, 4, 1]);
+        assert_eq!(merged.positions, vec![7, 1, 1, 3, 4, 0]);
+
+        let mut union = Union::new(vec![cursor(&ps1), cursor(&ps2)]);
+        assert_eq!(union.advance_to(3), Some(4));
+        assert_eq!(union.advance(), None);
+    }
+}
@@ -16,6 +16,19 @@ pub struct Postings<A, B, C> {
 
 pub type VecPostings = Postings<Vec<DocId>, Vec<DocId>, Vec<DocId>>;
 
+/// Positions are stored delta encoded within each document. Decode them in place given
+/// non-cumulated tfs
+pub fn decode_positions(postings: &mut VecPostings) {
+    let mut start = 0;
+    for &tf in &postings.tfs {
+        let end = start + tf as usize;
+        for i in start + 1..end {
+            postings.positions[i] += postings.positions[i - 1];
+        }
+        start = end;
+    }
+}
+
 pub trait PostingsStore {
     fn get_postings(&mut self, term_id: TermId) -> Option<VecPostings>;
 }
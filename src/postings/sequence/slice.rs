@@ -34,9 +34,13 @@ pub struct PlainEncoder<W> {
 impl<W: io::Write> PlainEncoder<W> {
     pub fn new(writer: W) -> PlainEncoder<W> {
         PlainEncoder {
-            writer: writer
+            writer
         }
     }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
 }
 
 impl<W: io::Write> SequenceEncoder for PlainEncoder<W> {
@@ -62,10 +66,15 @@ impl<'a> SliceSequence<'a> {
     pub fn new(seq: &'a [DocId]) -> Self {
         SliceSequence {
             position: 0,
-            seq: seq,
+            seq,
         }
     }
 
+    /// Underlying slice, including already consumed elements
+    pub fn as_slice(&self) -> &'a [DocId] {
+        self.seq
+    }
+
     fn get_at(&self) -> Option<DocId> {
         if self.position <= self.seq.len() {
             Some(self.seq[self.position - 1])
@@ -95,13 +104,35 @@ impl<'a> Sequence for SliceSequence<'a> {
     }
 
     fn remains(&self) -> usize {
-        self.seq.len() - self.position
+        self.seq.len().saturating_sub(self.position)
     }
 
     fn skip_n(&mut self, n: usize) -> Option<DocId> {
         self.position += n;
         self.get_at()
     }
+
+    /// Gallop over the rest of the slice: double the step until overshooting, then binary
+    /// search the last step
+    fn skip_to(&mut self, doc_id: DocId) -> (usize, Option<DocId>) {
+        if self.position > 0 && self.position <= self.seq.len() && self.current() == doc_id {
+            return (0, Some(doc_id));
+        }
+        let rest = &self.seq[self.position.min(self.seq.len())..];
+        let mut step = 1;
+        while step < rest.len() && rest[step - 1] < doc_id {
+            step *= 2;
+        }
+        let lo = step / 2;
+        let i = lo + rest[lo..step.min(rest.len())].partition_point(|&x| x < doc_id);
+        if i < rest.len() {
+            self.position += i + 1;
+            (i + 1, Some(rest[i]))
+        } else {
+            self.position += rest.len() + 1;
+            (rest.len(), None)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -123,7 +154,7 @@ mod tests {
 
     #[test]
     fn test_slice_sequence_skip() {
-        let docs = vec![5,7,9,11,15,17,50,90];
+        let docs = [5,7,9,11,15,17,50,90];
         let mut seq = (&docs[..]).to_sequence();
         assert_eq!(seq.next().unwrap(), 5);
         assert_eq!(seq.skip_to(9), (2, Some(9)));
@@ -162,6 +193,19 @@ mod tests {
         assert_eq!(subseq.skip_to(100000), (0, None));
     }
 
+    #[test]
+    fn test_slice_sequence_gallop() {
+        let docs = (0..1000).map(|x| x * 2).collect::<Vec<_>>();
+        let mut seq = (&docs[..]).to_sequence();
+        assert_eq!(seq.skip_to(0), (1, Some(0)));
+        assert_eq!(seq.skip_to(3), (2, Some(4)));
+        assert_eq!(seq.skip_to(1001), (499, Some(1002)));
+        assert_eq!(seq.skip_to(1002), (0, Some(1002)));
+        assert_eq!(seq.skip_to(1998), (498, Some(1998)));
+        assert_eq!(seq.skip_to(2000), (0, None));
+        assert_eq!(seq.skip_to(2000), (0, None));
+    }
+
     #[test]
     fn test_slice_sequence_skip_n() {
         let docs = vec![5,7,9,11,15,17,50,90, 120, 2000, 2001];
@@ -0,0 +1,62 @@
+/// Small deterministic pseudo-random generator (xorshift64*) for generated tests, corpora and
+/// benchmarks. The same seed always gives the same numbers. Not fit for cryptography
+#[derive(Clone, Debug)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        const GOLDEN: u64 = 0x9e37_79b9_7f4a_7c15;
+        let state = seed.wrapping_add(GOLDEN).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        // Zero state would stay zero forever
+        XorShift { state: if state == 0 { GOLDEN } else { state } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform number in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0);
+        self.next_u64() % n
+    }
+
+    /// Uniform number in `lo..hi`
+    pub fn range(&mut self, lo: u64, hi: u64) -> u64 {
+        lo + self.below(hi - lo)
+    }
+
+    /// Uniform number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let (mut a, mut b) = (XorShift::new(7), XorShift::new(7));
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+            let x = a.range(10, 20);
+            assert!((10..20).contains(&x));
+            assert_eq!(x, b.range(10, 20));
+            let f = a.next_f64();
+            assert!((0.0..1.0).contains(&f));
+            b.next_f64();
+        }
+        assert_ne!(XorShift::new(0).next_u64(), XorShift::new(1).next_u64());
+    }
+}
@@ -0,0 +1,793 @@
+use std::io;
+use std::ops::Range;
+use std::path::{Path,PathBuf};
+use std::slice;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::thread;
+use types::*;
+use index::{Manifest,Segment};
+use dictionary::{TermDictionary,TermPostings};
+use postings::{VecPostings,PostingsCursor,RawCursor,Union,Intersect,Filter,SliceSequence,DocMatch,decode_positions};
+use explain::{Explanation,SegmentExplanation,CursorStats};
+use cache::{QueryCache,QueryKey,CacheStats};
+
+/// Searches all live segments of an index. With more threads segments are searched
+/// concurrently and segments larger than the partition size are split into DocId ranges
+/// searched concurrently as well.
+pub struct Searcher {
+    directory: PathBuf,
+    generation: u64,
+    segments: Vec<Segment>,
+    threads: usize,
+    partition_size: DocId,
+    cache: Option<Mutex<QueryCache>>,
+    fst_lookups: bool,
+}
+
+/// Order of hits in a page of results
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    /// Ascending DocIds
+    DocId,
+    /// Descending score, ties broken by ascending DocId
+    Score,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub doc_id: DocId,
+    /// TF-IDF score. Zero when hits are ordered by DocId
+    pub score: f64,
+}
+
+/// One page of results of a query
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    /// Number of all matching documents
+    pub total: usize,
+    pub hits: Vec<Hit>,
+}
+
+/// Part of a query evaluation done by a single thread: documents of a segment in the DocId range
+struct Task<'s> {
+    segment: &'s Segment,
+    doc_ids: Range<DocId>,
+}
+
+impl Searcher {
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<Searcher> {
+        let directory = directory.as_ref();
+        let manifest = Manifest::read(directory)?;
+        Ok(Searcher {
+            directory: directory.to_path_buf(),
+            generation: manifest.generation,
+            segments: open_segments(directory, &manifest)?,
+            threads: 1,
+            partition_size: 1 << 16,
+            cache: None,
+            fst_lookups: false,
+        })
+    }
+
+    /// Switch to the current generation of the index if it changed since the searcher was
+    /// opened. Returns whether it did
+    pub fn reload(&mut self) -> io::Result<bool> {
+        let manifest = Manifest::read(&self.directory)?;
+        if manifest.generation == self.generation {
+            return Ok(false);
+        }
+        self.segments = open_segments(&self.directory, &manifest)?;
+        self.generation = manifest.generation;
+        if let Some(ref cache) = self.cache {
+            cache.lock().unwrap().retain_generation(self.generation);
+        }
+        Ok(true)
+    }
+
+    /// Cache results of `search` in at most `size` bytes of compressed doc lists. Zero turns
+    /// the cache off
+    pub fn set_cache_size(&mut self, size: usize) {
+        self.cache = if size > 0 { Some(Mutex::new(QueryCache::new(size))) } else { None };
+    }
+
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.lock().unwrap().stats())
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        assert!(threads > 0);
+        self.threads = threads;
+    }
+
+    /// Number of documents of a segment above which it gets split between threads
+    pub fn set_partition_size(&mut self, partition_size: DocId) {
+        assert!(partition_size > 0);
+        self.partition_size = partition_size;
+    }
+
+    /// Look query terms up in the `FstDictionary` of segments having one instead of their trie.
+    /// Explained queries use it too, their paths are recorded for trie lookups only
+    pub fn set_fst_lookups(&mut self, fst_lookups: bool) {
+        self.fst_lookups = fst_lookups;
+    }
+
+    /// Generation of the segments manifest the searcher was opened with
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Size of the global DocId space, deleted documents included
+    pub fn num_docs(&self) -> DocId {
+        self.segments.iter().map(|s| s.info.num_docs).sum()
+    }
+
+    pub fn num_deleted(&self) -> DocId {
+        self.segments.iter().map(|s| s.info.num_deleted).sum()
+    }
+
+    /// Find DocIds of documents containing all query terms. Unless `exact` is set, query terms
+    /// match as prefixes too. DocIds are global and ascending. Deleted documents never match.
+    /// Queries to be explained are evaluated on a single thread and bypass the cache
+    pub fn search<S: AsRef<str>>(&self, query: &[S], exact: bool, explanation: Option<&mut Explanation>) -> Vec<DocId> {
+        let cache = match self.cache {
+            Some(ref cache) if explanation.is_none() => cache,
+            _ => return self.search_uncached(query, exact, explanation),
+        };
+        let key = QueryKey::new(self.generation, query, exact);
+        if let Some(docs) = cache.lock().unwrap().get(&key) {
+            return docs;
+        }
+        let docs = self.search_uncached(query, exact, None);
+        cache.lock().unwrap().insert(key, &docs);
+        docs
+    }
+
+    fn search_uncached<S: AsRef<str>>(&self, query: &[S], exact: bool, mut explanation: Option<&mut Explanation>) -> Vec<DocId> {
+        let q = query.iter().map(|s| s.as_ref()).collect::<Vec<&str>>();
+        debug!("Searching query: {:?}", &q);
+        if self.threads > 1 && explanation.is_none() {
+            return self.search_parallel(&q, exact);
+        }
+
+        let mut result = Vec::new();
+        for segment in &self.segments {
+            let mut segment_explanation = explanation.as_ref().map(|_| SegmentExplanation::new(&segment.info.name));
+            let docs = search_segment(segment, &q, exact, self.fst_lookups, None, segment_explanation.as_mut());
+            result.extend(docs.into_iter().map(|doc| segment.base + doc));
+            if let (Some(explanation), Some(segment_explanation)) = (explanation.as_deref_mut(), segment_explanation) {
+                explanation.segments.push(segment_explanation);
+            }
+        }
+        result
+    }
+
+    /// Lazily iterate documents matching the query like `search`, with tfs and positions of every
+    /// query term
+    pub fn matches<'s, S: AsRef<str>>(&'s self, query: &[S], exact: bool) -> impl Iterator<Item = DocMatch> + 's {
+        let q = query.iter().map(|s| s.as_ref().to_string()).collect::<Vec<_>>();
+        let fst = self.fst_lookups;
+        self.segments.iter().flat_map(move |segment| {
+            let q = q.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            query_cursor(segment, &q, exact, fst, None, None).into_iter().flat_map(move |(cursor, order)| {
+                cursor.matches().map(move |doc_match| query_order(doc_match, segment.base, &order))
+            })
+        })
+    }
+
+    /// Page of at most `limit` hits following the first `offset` hits in the given order. Ties
+    /// are broken by DocIds, so pages of the same index never overlap
+    pub fn search_page<S: AsRef<str>>(&self, query: &[S], exact: bool, order: Order, offset: usize, limit: usize) -> Page {
+        let mut hits = match order {
+            Order::DocId => self.search(query, exact, None).into_iter().map(|doc_id| Hit { doc_id, score: 0.0 }).collect(),
+            Order::Score => self.scored(query, exact),
+        };
+        let total = hits.len();
+        if order == Order::Score {
+            let by_score = |a: &Hit, b: &Hit| b.score.total_cmp(&a.score).then(a.doc_id.cmp(&b.doc_id));
+            // Only the hits up to the end of the page need to be sorted
+            let end = offset.saturating_add(limit).min(total);
+            if end > 0 && end < total {
+                hits.select_nth_unstable_by(end - 1, by_score);
+                hits.truncate(end);
+            }
+            hits.sort_by(by_score);
+        }
+        let hits = hits.into_iter().skip(offset).take(limit).collect();
+        Page { total, hits }
+    }
+
+    /// All matching documents scored by the sum of their query terms' tf * idf
+    fn scored<S: AsRef<str>>(&self, query: &[S], exact: bool) -> Vec<Hit> {
+        let q = query.iter().map(|s| s.as_ref()).collect::<Vec<&str>>();
+        // Document frequencies over all segments, deleted documents included, whether or not
+        // the segment has the other terms. Documents in several lists of a term are counted once
+        let mut dfs = vec![0; q.len()];
+        for segment in &self.segments {
+            let dict = segment.term_dict(self.fst_lookups);
+            for (df, term) in dfs.iter_mut().zip(&q) {
+                if let Some(postings) = find_term(&*dict, exact, term) {
+                    *df += match postings.lists.len() {
+                        1 => postings.num_postings(),
+                        _ => TermCursor::new(segment, &postings, None).doc_ids().count() as u64,
+                    };
+                }
+            }
+        }
+        let num_docs = self.num_docs() as f64;
+        let idfs = dfs.iter().map(|&df| (1.0 + num_docs / df.max(1) as f64).ln()).collect::<Vec<_>>();
+
+        self.matches(query, exact).map(|doc_match| {
+            let score = doc_match.postings.tfs.iter().zip(&idfs).map(|(&tf, idf)| tf as f64 * idf).sum();
+            Hit { doc_id: doc_match.doc_id, score }
+        }).collect()
+    }
+
+    /// Visit documents matching the query one by one, reading their postings only on demand
+    pub fn match_cursor<S: AsRef<str>>(&self, query: &[S], exact: bool) -> MatchCursor<'_> {
+        MatchCursor {
+            segments: self.segments.iter(),
+            query: query.iter().map(|s| s.as_ref().to_string()).collect(),
+            exact,
+            fst: self.fst_lookups,
+            current: None,
+            doc_match: None,
+        }
+    }
+
+    /// Lazily iterate DocIds of documents matching the query like `search`
+    pub fn doc_ids<'s, S: AsRef<str>>(&'s self, query: &[S], exact: bool) -> impl Iterator<Item = DocId> + 's {
+        let q = query.iter().map(|s| s.as_ref().to_string()).collect::<Vec<_>>();
+        let fst = self.fst_lookups;
+        self.segments.iter().flat_map(move |segment| {
+            let q = q.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            query_cursor(segment, &q, exact, fst, None, None).into_iter().flat_map(move |(cursor, _)| {
+                cursor.doc_ids().map(move |doc_id| segment.base + doc_id)
+            })
+        })
+    }
+
+    /// Number of documents matching the query. Postings of the matches are not read
+    pub fn count<S: AsRef<str>>(&self, query: &[S], exact: bool) -> usize {
+        self.doc_ids(query, exact).count()
+    }
+
+    fn tasks(&self) -> Vec<Task<'_>> {
+        let mut tasks = Vec::new();
+        for segment in &self.segments {
+            let num_docs = segment.info.num_docs;
+            let mut start = 1;
+            while start <= num_docs {
+                let end = if num_docs - start >= self.partition_size { start + self.partition_size } else { num_docs + 1 };
+                tasks.push(Task { segment, doc_ids: start..end });
+                start = end;
+            }
+        }
+        tasks
+    }
+
+    fn search_parallel(&self, q: &[&str], exact: bool) -> Vec<DocId> {
+        let tasks = self.tasks();
+        let next_task = AtomicUsize::new(0);
+        let mut results = thread::scope(|scope| {
+            let workers = (0..self.threads.min(tasks.len())).map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let i = next_task.fetch_add(1, Ordering::Relaxed);
+                    let task = match tasks.get(i) {
+                        Some(task) => task,
+                        None => return done,
+                    };
+                    let docs = search_segment(task.segment, q, exact, self.fst_lookups, Some(task.doc_ids.clone()), None);
+                    done.push((i, docs));
+                }
+            })).collect::<Vec<_>>();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect::<Vec<_>>()
+        });
+
+        // Tasks are ordered by DocIds
+        results.sort_by_key(|&(i, _)| i);
+        let mut result = Vec::new();
+        for (i, docs) in results {
+            let base = tasks[i].segment.base;
+            result.extend(docs.into_iter().map(|doc| base + doc));
+        }
+        result
+    }
+}
+
+fn open_segments(directory: &Path, manifest: &Manifest) -> io::Result<Vec<Segment>> {
+    let offsets = manifest.doc_offsets();
+    manifest.segments.iter().zip(offsets).map(|(info, base)| {
+        Segment::open(&directory.join(&info.name), info.clone(), base)
+    }).collect()
+}
+
+fn find_terms(dict: &dyn TermDictionary, exact: bool, query: &[&str]) -> Option<Vec<TermPostings>> {
+    query.iter().map(|term| find_term(dict, exact, term)).collect()
+}
+
+fn find_term(dict: &dyn TermDictionary, exact: bool, term: &str) -> Option<TermPostings> {
+    if exact { dict.find_exact(term) } else { dict.find_prefix(term) }
+}
+
+/// Like `find_terms` in the dictionary `Segment::term_dict` chooses, recording the lookups
+/// made. Paths are recorded for trie lookups only
+fn explain_terms(segment: &Segment, exact: bool, fst: bool, query: &[&str], explanation: &mut SegmentExplanation) -> Option<Vec<TermPostings>> {
+    if let Some(fst_dict) = segment.fst_dict().filter(|_| fst) {
+        return query.iter().map(|term| {
+            let postings = find_term(&fst_dict, exact, term);
+            explanation.fst_lookup(term, postings.as_ref());
+            postings
+        }).collect();
+    }
+    let dict = segment.dict();
+    query.iter().map(|term| {
+        let node = explanation.find_term(&dict, term, exact)?;
+        let postings = dict.prefix_postings(&node);
+        if postings.lists.is_empty() { None } else { Some(postings) }
+    }).collect()
+}
+
+/// Documents matching a query, visited one by one. Positions of a document are read only when
+/// asked for, eg. by a phrase or proximity check or for highlighting
+pub struct MatchCursor<'s> {
+    segments: slice::Iter<'s, Segment>,
+    query: Vec<String>,
+    exact: bool,
+    fst: bool,
+    current: Option<(&'s Segment, QueryCursor<'s>, Vec<usize>)>,
+    doc_match: Option<DocMatch>,
+}
+
+impl<'s> MatchCursor<'s> {
+    /// Move to the next matching document and return its global DocId
+    pub fn next_doc(&mut self) -> Option<DocId> {
+        self.doc_match = None;
+        loop {
+            if let Some((segment, ref mut cursor, _)) = self.current {
+                if let Some(doc_id) = cursor.advance() {
+                    return Some(segment.base + doc_id);
+                }
+            }
+            self.current = None;
+            let segment = self.segments.next()?;
+            let q = self.query.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            self.current = query_cursor(segment, &q, self.exact, self.fst, None, None).map(|(cursor, order)| (segment, cursor, order));
+        }
+    }
+
+    /// Postings of the current document in query order, with decoded positions. They are read
+    /// on the first call for the document
+    pub fn postings(&mut self) -> &DocMatch {
+        if self.doc_match.is_none() {
+            let (segment, ref mut cursor, ref order) = *self.current.as_mut().expect("No current document");
+            let mut postings = VecPostings {
+                docs: Vec::new(),
+                tfs: Vec::new(),
+                positions: Vec::new(),
+            };
+            cursor.catch_up(&mut postings);
+            let doc_id = unsafe {cursor.current()};
+            self.doc_match = Some(query_order(DocMatch { doc_id, postings }, segment.base, order));
+        }
+        self.doc_match.as_ref().unwrap()
+    }
+}
+
+type SegmentCursor<'s> = RawCursor<SliceSequence<'s>, SliceSequence<'s>, SliceSequence<'s>>;
+
+/// Cursor over the postings of a query term, read from a single list or merged from several
+enum TermCursor<'s> {
+    List(SegmentCursor<'s>),
+    Union(Union<SegmentCursor<'s>>),
+}
+
+impl<'s> TermCursor<'s> {
+    fn new(segment: &'s Segment, postings: &TermPostings, doc_ids: Option<&Range<DocId>>) -> Self {
+        let mut cursors = postings.lists.iter().map(|list| {
+            RawCursor::new(match doc_ids {
+                Some(doc_ids) => segment.list_postings_in(list, doc_ids.clone()),
+                None => segment.list_postings(list),
+            })
+        }).collect::<Vec<_>>();
+        if cursors.len() == 1 {
+            TermCursor::List(cursors.pop().unwrap())
+        } else {
+            TermCursor::Union(Union::new(cursors))
+        }
+    }
+}
+
+impl<'s> PostingsCursor for TermCursor<'s> {
+    type DS = SliceSequence<'s>;
+    type TS = SliceSequence<'s>;
+    type PS = SliceSequence<'s>;
+
+    unsafe fn current(&self) -> DocId {
+        match *self {
+            TermCursor::List(ref cursor) => cursor.current(),
+            TermCursor::Union(ref cursor) => cursor.current(),
+        }
+    }
+
+    fn remains(&self) -> usize {
+        match *self {
+            TermCursor::List(ref cursor) => cursor.remains(),
+            TermCursor::Union(ref cursor) => cursor.remains(),
+        }
+    }
+
+    fn advance(&mut self) -> Option<DocId> {
+        match *self {
+            TermCursor::List(ref mut cursor) => cursor.advance(),
+            TermCursor::Union(ref mut cursor) => cursor.advance(),
+        }
+    }
+
+    fn advance_to(&mut self, doc_id: DocId) -> Option<DocId> {
+        match *self {
+            TermCursor::List(ref mut cursor) => cursor.advance_to(doc_id),
+            TermCursor::Union(ref mut cursor) => cursor.advance_to(doc_id),
+        }
+    }
+
+    fn catch_up(&mut self, result: &mut VecPostings) -> usize {
+        match *self {
+            TermCursor::List(ref mut cursor) => cursor.catch_up(result),
+            TermCursor::Union(ref mut cursor) => cursor.catch_up(result),
+        }
+    }
+}
+
+type QueryCursor<'s> = Filter<'s, Intersect<TermCursor<'s>>>;
+
+/// Cursor over live documents of the segment containing all query terms, or None when a term
+/// is missing. Terms are looked up in the `FstDictionary` if `fst` is set and the segment has
+/// one. Only documents in the DocId range are visited if it is given. Term cursors are
+/// intersected ascending by their size, returned order maps them to query terms
+fn query_cursor<'s>(segment: &'s Segment, q: &[&str], exact: bool, fst: bool, doc_ids: Option<Range<DocId>>,
+                    mut explanation: Option<&mut SegmentExplanation>) -> Option<(QueryCursor<'s>, Vec<usize>)> {
+    if q.is_empty() {
+        return None;
+    }
+    let term_postings = match explanation {
+        Some(ref mut explanation) => explain_terms(segment, exact, fst, q, explanation)?,
+        None => find_terms(&*segment.term_dict(fst), exact, q)?,
+    };
+
+    let mut term_cursors = q.iter().zip(term_postings).enumerate().map(|(i, (term, postings))| {
+        debug!("Term found. segment={}, term='{}', lists={}, numdocs={}", segment.info.name, term, postings.lists.len(), postings.num_postings());
+        (i, TermCursor::new(segment, &postings, doc_ids.as_ref()))
+    }).collect::<Vec<_>>();
+
+    // sort sequences ascending by their size to make daat skipping much faster
+    term_cursors.sort_by(|a, b| {
+        a.1.remains().cmp(&b.1.remains())
+    });
+    if let Some(explanation) = explanation {
+        for &(i, ref cursor) in &term_cursors {
+            explanation.cursors.push(CursorStats { term: q[i].into(), postings: cursor.remains(), skipped: 0 });
+        }
+    }
+    let (order, cursors): (Vec<_>, Vec<_>) = term_cursors.into_iter().unzip();
+    Some((Filter::new(Intersect::new(cursors), segment.deleted()), order))
+}
+
+/// Search the segment, or only its documents in the DocId range if given
+fn search_segment(segment: &Segment, q: &[&str], exact: bool, fst: bool, doc_ids: Option<Range<DocId>>,
+                  mut explanation: Option<&mut SegmentExplanation>) -> Vec<DocId> {
+    let (mut cursor, _) = match query_cursor(segment, q, exact, fst, doc_ids, explanation.as_deref_mut()) {
+        Some(cursor) => cursor,
+        None => return Vec::new(),
+    };
+    let mut result = Vec::new();
+    while let Some(doc_id) = cursor.advance() {
+        result.push(doc_id);
+    }
+    if let Some(explanation) = explanation {
+        let intersect = cursor.inner();
+        explanation.matches = intersect.matches();
+        explanation.deleted = intersect.matches() - result.len();
+        for (stats, skipped) in explanation.cursors.iter_mut().zip(intersect.skip_counts()) {
+            stats.skipped = skipped;
+        }
+    }
+    result
+}
+
+/// Put postings of a match, coming in order of the intersected cursors, into query order and
+/// decode their positions
+fn query_order(doc_match: DocMatch, base: DocId, order: &[usize]) -> DocMatch {
+    let p = doc_match.postings;
+    let mut starts = Vec::with_capacity(p.tfs.len());
+    let mut start = 0;
+    for &tf in &p.tfs {
+        starts.push(start);
+        start += tf as usize;
+    }
+    let mut by_query = vec![0; order.len()];
+    for (k, &i) in order.iter().enumerate() {
+        by_query[i] = k;
+    }
+
+    let doc_id = base + doc_match.doc_id;
+    let mut postings = VecPostings {
+        docs: vec![doc_id; order.len()],
+        tfs: Vec::with_capacity(order.len()),
+        positions: Vec::with_capacity(p.positions.len()),
+    };
+    for k in by_query {
+        postings.tfs.push(p.tfs[k]);
+        postings.positions.extend_from_slice(&p.positions[starts[k]..starts[k] + p.tfs[k] as usize]);
+    }
+    decode_positions(&mut postings);
+    DocMatch { doc_id, postings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nutrie::PrefixPostings;
+    use index::NoMergePolicy;
+    use test_support::{TempDir,DOCS};
+
+    #[test]
+    fn test_search_across_segments() {
+        let dir = TempDir::new("searcher");
+
+        let mut writer = dir.writer();
+        assert_eq!(writer.add_document(&["apple", "banana", "cherry"]).unwrap(), Some(1));
+        assert_eq!(writer.add_document(&["", ""]).unwrap(), None);
+        assert_eq!(writer.add_document(&["apple", "apricot"]).unwrap(), Some(2));
+        assert_eq!(writer.add_document(&["banana", "band", "bandana"]).unwrap(), Some(3));
+        writer.commit().unwrap();
+        assert!(writer.commit().unwrap().is_none());
+
+        assert_eq!(writer.add_document(&["apple", "banana"]).unwrap(), Some(4));
+        assert_eq!(writer.add_document(&["kiwi"]).unwrap(), Some(5));
+        writer.commit().unwrap();
+        assert_eq!(writer.manifest().segments.len(), 2);
+        assert_eq!(writer.manifest().generation, 2);
+
+        let searcher = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.num_docs(), 5);
+        assert_eq!(searcher.search(&["apple", "banana"], true, None), vec![1, 4]);
+        assert_eq!(searcher.search(&["ban"], false, None), vec![1, 3, 4]);
+        assert_eq!(searcher.search(&["ban"], true, None), Vec::<DocId>::new());
+        assert_eq!(searcher.search(&["kiwi"], true, None), vec![5]);
+        assert_eq!(searcher.search(&["kiwi", "apple"], true, None), Vec::<DocId>::new());
+
+        let mut explanation = Explanation::new();
+        searcher.search(&["apple"], true, Some(&mut explanation));
+        assert_eq!(explanation.segments.len(), 2);
+        assert_eq!(explanation.segments[0].matches, 2);
+    }
+
+    #[test]
+    fn test_streaming_matches() {
+        let dir = TempDir::new("matches");
+
+        let mut writer = dir.writer();
+        writer.add_document(&["apple", "x", "banana", "apple"]).unwrap();
+        writer.add_document(&["banana", "kiwi"]).unwrap();
+        writer.commit().unwrap();
+        writer.add_document(&["kiwi", "banana", "x", "x", "apple", "banana"]).unwrap();
+        writer.commit().unwrap();
+
+        let searcher = Searcher::open(&dir).unwrap();
+        let matches = searcher.matches(&["apple", "banana"], true).collect::<Vec<_>>();
+        assert_eq!(matches.iter().map(|m| m.doc_id).collect::<Vec<_>>(), vec![1, 3]);
+        // Postings come in query order with absolute positions
+        assert_eq!(matches[0].postings.docs, vec![1, 1]);
+        assert_eq!(matches[0].postings.tfs, vec![2, 1]);
+        assert_eq!(matches[0].postings.positions, vec![0, 3, 2]);
+        assert_eq!(matches[1].postings.tfs, vec![1, 2]);
+        assert_eq!(matches[1].postings.positions, vec![4, 1, 5]);
+
+        assert_eq!(searcher.matches(&["banana"], true).take(1).map(|m| m.doc_id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(searcher.doc_ids(&["kiwi"], true).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(searcher.count(&["banana"], true), 3);
+        assert_eq!(searcher.count(&["nope"], true), 0);
+
+        // Positions of the second match only
+        let mut cursor = searcher.match_cursor(&["apple", "banana"], true);
+        assert_eq!(cursor.next_doc(), Some(1));
+        assert_eq!(cursor.next_doc(), Some(3));
+        assert_eq!(cursor.postings().postings.positions, vec![4, 1, 5]);
+        assert_eq!(cursor.postings().doc_id, 3);
+        assert_eq!(cursor.next_doc(), None);
+    }
+
+    #[test]
+    fn test_pages() {
+        let dir = TempDir::new("pages");
+
+        let mut writer = dir.writer();
+        writer.add_document(&["kiwi", "apple"]).unwrap();
+        writer.add_document(&["apple", "apple", "apple"]).unwrap();
+        writer.add_document(&["apple"]).unwrap();
+        writer.commit().unwrap();
+        writer.add_document(&["apple", "apple"]).unwrap();
+        writer.add_document(&["apple", "kiwi"]).unwrap();
+        writer.commit().unwrap();
+
+        let searcher = Searcher::open(&dir).unwrap();
+        let doc_ids = |page: Page| page.hits.iter().map(|h| h.doc_id).collect::<Vec<_>>();
+        let page = searcher.search_page(&["apple"], true, Order::DocId, 1, 2);
+        assert_eq!(page.total, 5);
+        assert_eq!(doc_ids(page), vec![2, 3]);
+        assert_eq!(doc_ids(searcher.search_page(&["apple"], true, Order::DocId, 4, 10)), vec![5]);
+        assert!(searcher.search_page(&["apple"], true, Order::DocId, 10, 10).hits.is_empty());
+
+        // Equal scores come in DocId order
+        let all = doc_ids(searcher.search_page(&["apple"], true, Order::Score, 0, 10));
+        assert_eq!(all, vec![2, 4, 1, 3, 5]);
+        let mut paged = Vec::new();
+        for offset in 0..3 {
+            paged.extend(doc_ids(searcher.search_page(&["apple"], true, Order::Score, offset * 2, 2)));
+        }
+        assert_eq!(paged, all);
+
+        let page = searcher.search_page(&["apple", "kiwi"], true, Order::Score, 0, 1);
+        assert_eq!(page.total, 2);
+        assert_eq!(page.hits[0].doc_id, 1);
+        assert!(page.hits[0].score > 0.0);
+    }
+
+    #[test]
+    fn test_scores_across_segments() {
+        let single = TempDir::new("scores-single");
+        let mut writer = single.writer();
+        for doc in DOCS {
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+
+        // Only the last segment has kiwi, the first lacks cherry
+        let split = TempDir::new("scores-split");
+        let mut writer = split.writer();
+        writer.set_merge_policy(NoMergePolicy);
+        for chunk in DOCS.chunks(2) {
+            for doc in chunk {
+                writer.add_document(doc).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let (single, split) = (Searcher::open(&single).unwrap(), Searcher::open(&split).unwrap());
+        assert_eq!(split.segments().len(), 3);
+        for &(query, exact) in &[(&["a", "k"][..], false), (&["banana", "cherry"], true), (&["ban", "ch"], false)] {
+            let page = single.search_page(query, exact, Order::Score, 0, 10);
+            assert!(page.total > 0);
+            assert_eq!(split.search_page(query, exact, Order::Score, 0, 10), page, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = TempDir::new("cache");
+
+        let mut writer = dir.writer();
+        writer.add_document(&["kiwi", "apple"]).unwrap();
+        writer.add_document(&["apple"]).unwrap();
+        writer.commit().unwrap();
+
+        let mut searcher = Searcher::open(&dir).unwrap();
+        searcher.set_cache_size(1 << 10);
+        assert_eq!(searcher.search(&["apple", "kiwi"], true, None), vec![1]);
+        assert_eq!(searcher.search(&["kiwi", "apple", "kiwi"], true, None), vec![1]);
+        assert_eq!(searcher.search(&["kiwi", "apple"], false, None), vec![1]);
+        let stats = searcher.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+
+        assert!(!searcher.reload().unwrap());
+        writer.add_document(&["kiwi", "apple"]).unwrap();
+        writer.delete_document(1).unwrap();
+        writer.commit().unwrap();
+        assert!(searcher.reload().unwrap());
+        assert_eq!(searcher.cache_stats().unwrap().entries, 0);
+        let fresh = Searcher::open(&dir).unwrap();
+        assert_eq!(searcher.search(&["apple", "kiwi"], true, None), fresh.search(&["apple", "kiwi"], true, None));
+        assert_eq!(searcher.count(&["apple", "kiwi"], true), 1);
+    }
+
+    #[test]
+    fn test_fst_lookups() {
+        let dir = TempDir::new("fst-lookups");
+
+        let mut writer = dir.writer();
+        writer.set_fst_dictionary(true);
+        writer.add_document(&["band", "x", "banana", "band"]).unwrap();
+        writer.add_document(&["bandana", "kiwi"]).unwrap();
+        writer.add_document(&["kiwi", "ban"]).unwrap();
+        writer.commit().unwrap();
+
+        let trie = Searcher::open(&dir).unwrap();
+        let mut fst = Searcher::open(&dir).unwrap();
+        fst.set_fst_lookups(true);
+        assert_eq!(fst.search(&["ban"], false, None), vec![1, 2, 3]);
+        assert_eq!(fst.search(&["ban"], true, None), vec![3]);
+        for query in &[&["ban"][..], &["band", "b"], &["kiwi", "banda"], &["nope"]] {
+            let matches = |searcher: &Searcher| searcher.matches(query, false).map(|m| (m.doc_id, m.postings.tfs, m.postings.positions)).collect::<Vec<_>>();
+            assert_eq!(matches(&fst), matches(&trie));
+            for &exact in &[true, false] {
+                assert_eq!(fst.search_page(query, exact, Order::Score, 0, 10), trie.search_page(query, exact, Order::Score, 0, 10));
+            }
+        }
+        // Positions of all terms starting with the prefix, merged
+        let first = fst.matches(&["ban"], false).next().unwrap();
+        assert_eq!(first.postings.positions, vec![0, 2, 3]);
+
+        let mut explanation = Explanation::new();
+        assert_eq!(fst.search(&["ban", "nope"], false, Some(&mut explanation)), Vec::<DocId>::new());
+        let lookups = &explanation.segments[0].lookups;
+        assert_eq!(lookups.iter().map(|lookup| lookup.fst_lists).collect::<Vec<_>>(), vec![Some(4), Some(0)]);
+        assert!(lookups.iter().all(|lookup| lookup.path.is_empty()));
+    }
+
+    #[test]
+    fn test_prefix_postings() {
+        for &policy in &[PrefixPostings::All, PrefixPostings::AllButRoot, PrefixPostings::AtLeast(2), PrefixPostings::AtLeast(3),
+                         PrefixPostings::None,
+                         PrefixPostings::Bounded { max_lists: 2, min_docs: 1 }] {
+            let dir = TempDir::new(&format!("prefix-postings-{:?}", policy));
+            let mut writer = dir.writer();
+            writer.set_prefix_postings(policy);
+            writer.add_document(&["band", "x", "banana", "band"]).unwrap();
+            writer.add_document(&["bandana", "kiwi"]).unwrap();
+            writer.add_document(&["kiwi", "ban"]).unwrap();
+            writer.commit().unwrap();
+
+            let searcher = Searcher::open(&dir).unwrap();
+            let dict = searcher.segments()[0].dict();
+            assert_eq!(dict.root().num_postings > 0, policy == PrefixPostings::All, "{:?}", policy);
+            let b = dict.find_term("b", true).unwrap();
+            assert_eq!(b.num_postings > 0, policy != PrefixPostings::None, "{:?}", policy);
+            assert_eq!(dict.prefix_postings(&b).lists.len(), if policy == PrefixPostings::None { 4 } else { 1 });
+            // Band and bandana are merged from two lists in two documents, under b left to its
+            // parent by the third policy
+            let band = dict.find_term("band", false).unwrap();
+            assert_eq!(band.num_postings > 0, !matches!(policy, PrefixPostings::None | PrefixPostings::AtLeast(3) | PrefixPostings::Bounded { .. }));
+
+            assert_eq!(searcher.search(&[""], false, None), vec![1, 2, 3], "{:?}", policy);
+            assert_eq!(searcher.search(&["b"], false, None), vec![1, 2, 3]);
+            assert_eq!(searcher.search(&["band", "k"], false, None), vec![2]);
+            assert_eq!(searcher.search(&["ban"], true, None), vec![3]);
+            let first = searcher.matches(&["ban"], false).next().unwrap();
+            assert_eq!(first.postings.positions, vec![0, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_parallel_search() {
+        let dir = TempDir::new("parallel");
+
+        let mut writer = dir.writer();
+        for i in 0..100 {
+            let doc = (0..i % 5 + 1).map(|j| format!("w{}", (i + j * 7) % 13)).collect::<Vec<_>>();
+            writer.add_document(&doc).unwrap();
+            if i % 30 == 0 {
+                writer.commit().unwrap();
+            }
+        }
+        writer.commit().unwrap();
+        writer.delete_document(42).unwrap();
+        writer.commit().unwrap();
+
+        let sequential = Searcher::open(&dir).unwrap();
+        let mut parallel = Searcher::open(&dir).unwrap();
+        parallel.set_threads(3);
+        parallel.set_partition_size(7);
+        for query in &[&["w1"][..], &["w1", "w8"], &["w"], &["w3", "w1", "w12"], &["nope"]] {
+            assert_eq!(parallel.search(query, false, None), sequential.search(query, false, None));
+            assert_eq!(parallel.search(query, true, None), sequential.search(query, true, None));
+        }
+        assert!(!parallel.search(&["w1"], true, None).is_empty());
+        assert!(!parallel.search(&["w"], false, None).contains(&42));
+    }
+}
@@ -1,7 +1,7 @@
-use std::mem;
 use types::*;
 use postings::{Postings,VecPostings,PostingsStore};
 
+#[derive(Default)]
 pub struct TermBuf {
     pub buffers: Vec<Option<Vec<DocId>>>,
     max_term_id: TermId,
@@ -28,9 +28,9 @@ impl TermBuf {
         if term_id > self.max_term_id {
             None
         } else {
-            let buffer = mem::replace(&mut self.buffers[term_id as usize], None);
+            let buffer = self.buffers[term_id as usize].take();
             assert!(buffer.is_some());
-            assert!(buffer.as_ref().unwrap().len() > 0);
+            assert!(!buffer.as_ref().unwrap().is_empty());
             buffer
         }
     }
@@ -41,7 +41,7 @@ impl TermBuf {
             panic!();
         }
 
-        let _ = mem::replace(&mut self.buffers[term_id as usize], Some(buf));
+        self.buffers[term_id as usize] = Some(buf);
     }
 }
 
@@ -0,0 +1,52 @@
+use std::{env,fs,process};
+use std::ops::Deref;
+use std::path::{Path,PathBuf};
+use index::IndexWriter;
+
+/// Small corpus with shared prefixes, repeated terms and an empty term
+pub static DOCS: &[&[&str]] = &[
+    &["apple", "banana", "cherry"],
+    &["apple", "apricot", "apple"],
+    &["banana", "band", "bandana", "", "band"],
+    &["apple", "banana"],
+    &["kiwi", "ban"],
+    &["cherry", "apricot", "kiwi", "banana"],
+];
+
+/// Index directory in the temp dir, unique to the test process by its name. Leftovers of an
+/// earlier run are removed first, the directory itself when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("sirka-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        TempDir { path }
+    }
+
+    pub fn writer(&self) -> IndexWriter {
+        IndexWriter::open(&self.path).unwrap()
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -20,7 +20,7 @@ pub fn first_letter(s: &str) -> u32 {
 
 pub fn typed_to_bytes<T>(slice: &[T]) -> &[u8] {
     unsafe {
-        slice::from_raw_parts(slice.as_ptr() as *const u8, slice.len() * mem::size_of::<T>())
+        slice::from_raw_parts(slice.as_ptr() as *const u8, mem::size_of_val(slice))
     }
 }
 
@@ -30,12 +30,40 @@ pub fn bytes_to_typed<T>(buf: &[u8]) -> &[T] {
     }
 }
 
+/// Append `x` in 7 bit groups, the lowest first, with the high bit set on all but the last
+pub fn write_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+/// Read a varint starting at `*at` and move `*at` past it
+#[inline]
+pub fn read_varint(bs: &[u8], at: &mut usize) -> u64 {
+    let b = bs[*at];
+    if b < 0x80 {
+        *at += 1;
+        return b as u64;
+    }
+    let mut x = (b & 0x7f) as u64;
+    for (i, &b) in bs[*at + 1..].iter().enumerate() {
+        x |= ((b & 0x7f) as u64) << (7 * (i + 1));
+        if b < 0x80 {
+            *at += i + 2;
+            return x;
+        }
+    }
+    panic!("truncated varint")
+}
+
 pub fn align_to(n: usize, alignment: usize) -> usize {
     (alignment - 1) - (n + alignment - 1) % alignment
 }
 
 pub fn is_sorted_ascending<T: PartialOrd>(seq: &[T]) -> bool {
-    if seq.len() < 1 {
+    if seq.is_empty() {
         return true;
     }
     let mut previous = &seq[0];
@@ -45,5 +73,5 @@ pub fn is_sorted_ascending<T: PartialOrd>(seq: &[T]) -> bool {
         }
         previous = item;
     }
-    return true;
+    true
 }
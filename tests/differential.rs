@@ -0,0 +1,145 @@
+//! Random corpora indexed in several commits with deletes and merges, queried through the
+//! library and compared against a brute-force scan of the documents, like `test.py` does
+
+extern crate sirka;
+
+use std::collections::BTreeSet;
+use std::{env,fs,process};
+use std::path::Path;
+use sirka::*;
+use sirka::random::XorShift;
+
+const ALPHABET: &[u8] = b"abcde";
+
+/// Terms over a small alphabet, so that many of them share prefixes
+fn vocabulary(rng: &mut XorShift, size: u64) -> Vec<String> {
+    let mut terms = BTreeSet::new();
+    while (terms.len() as u64) < size {
+        let term = (0..rng.range(1, 6)).map(|_| ALPHABET[rng.below(ALPHABET.len() as u64) as usize] as char).collect::<String>();
+        terms.insert(term);
+    }
+    terms.into_iter().collect()
+}
+
+/// Skewed choice, terms at the start of the vocabulary are the frequent ones
+fn pick<'a>(rng: &mut XorShift, vocabulary: &'a [String]) -> &'a str {
+    let n = rng.range(1, vocabulary.len() as u64 + 1);
+    &vocabulary[rng.below(n) as usize]
+}
+
+/// Every document carries its identity as a term no query can match, so that its current
+/// DocId can be looked up after merges renumbered it
+fn id_term(id: usize) -> String {
+    format!("#{}", id)
+}
+
+struct Model {
+    /// Terms of every added document and whether it is still live
+    docs: Vec<(Vec<String>, bool)>,
+}
+
+impl Model {
+    fn matches(&self, query: &[String], exact: bool) -> Vec<usize> {
+        self.docs.iter().enumerate().filter(|&(_, &(ref terms, live))| {
+            live && query.iter().all(|q| terms.iter().any(|t| if exact { t == q } else { t.starts_with(q.as_str()) }))
+        }).map(|(id, _)| id).collect()
+    }
+}
+
+fn random_query(rng: &mut XorShift, vocabulary: &[String]) -> Vec<String> {
+    (0..rng.range(1, 4)).map(|_| {
+        let term = pick(rng, vocabulary);
+        match rng.below(4) {
+            0 => term[..rng.below(term.len() as u64 + 1) as usize].to_string(),
+            1 => format!("{}z", term),
+            _ => term.to_string(),
+        }
+    }).collect()
+}
+
+fn check(dir: &Path, rng: &mut XorShift, model: &Model, vocabulary: &[String], what: &str) {
+    let mut searcher = Searcher::open(dir).unwrap();
+    searcher.set_threads(rng.range(1, 4) as usize);
+    searcher.set_partition_size(rng.range(1, 50));
+    if rng.chance(0.5) {
+        searcher.set_cache_size(1 << 12);
+    }
+    searcher.set_fst_lookups(rng.chance(0.5));
+
+    // Current DocIds of the documents
+    let doc_ids = (0..model.docs.len()).map(|id| {
+        let found = searcher.search(&[id_term(id)], true, None);
+        assert!(found.len() <= 1);
+        found.first().cloned()
+    }).collect::<Vec<_>>();
+    for (id, &(_, live)) in model.docs.iter().enumerate() {
+        assert_eq!(doc_ids[id].is_some(), live, "{}: document {} live", what, id);
+    }
+
+    for _ in 0..200 {
+        let query = random_query(rng, vocabulary);
+        let exact = rng.chance(0.5);
+        let mut expected = model.matches(&query, exact).into_iter().map(|id| doc_ids[id].unwrap()).collect::<Vec<_>>();
+        expected.sort();
+
+        let found = searcher.search(&query, exact, None);
+        assert_eq!(found, expected, "{}: query {:?}, exact {}", what, query, exact);
+        assert_eq!(searcher.doc_ids(&query, exact).collect::<Vec<_>>(), expected, "{}: doc ids of {:?}", what, query);
+        assert_eq!(searcher.count(&query, exact), expected.len(), "{}: count of {:?}", what, query);
+        let page = searcher.search_page(&query, exact, Order::Score, 0, 5);
+        assert_eq!(page.total, expected.len());
+        assert!(page.hits.iter().all(|hit| expected.binary_search(&hit.doc_id).is_ok()));
+    }
+}
+
+fn run(seed: u64) {
+    let dir = env::temp_dir().join(format!("sirka-differential-{}-{}", process::id(), seed));
+    let _ = fs::remove_dir_all(&dir);
+    let mut rng = XorShift::new(seed);
+    let size = rng.range(5, 60);
+    let vocabulary = vocabulary(&mut rng, size);
+    let mut model = Model { docs: Vec::new() };
+
+    let mut writer = IndexWriter::open(&dir).unwrap();
+    writer.set_merge_policy(LogMergePolicy { merge_factor: 3, min_merge_docs: 10, ..Default::default() });
+    writer.set_threads(rng.range(1, 4) as usize);
+    if rng.chance(0.3) {
+        writer.set_memory_budget(Some(1 << 10));
+    }
+    writer.set_compact_trie(rng.chance(0.5));
+    writer.set_fst_dictionary(rng.chance(0.5));
+    writer.set_prefix_postings(match rng.below(5) {
+        0 => PrefixPostings::All,
+        1 => PrefixPostings::AtLeast(rng.range(1, 20)),
+        2 => PrefixPostings::None,
+        3 => PrefixPostings::Bounded { max_lists: rng.range(1, 6), min_docs: rng.range(1, 10) },
+        _ => PrefixPostings::AllButRoot,
+    });
+    for _ in 0..rng.range(1, 8) {
+        let mut batch = Vec::new();
+        for _ in 0..rng.below(40) {
+            let mut terms = (0..rng.range(1, 8)).map(|_| pick(&mut rng, &vocabulary).to_string()).collect::<Vec<_>>();
+            terms.push(id_term(model.docs.len() + batch.len()));
+            batch.push(terms);
+        }
+        let doc_ids = writer.add_documents(&batch).unwrap();
+        for (terms, doc_id) in batch.into_iter().zip(doc_ids) {
+            // Delete some of the new documents before they get renumbered by merges
+            let live = !rng.chance(0.2) || !writer.delete_document(doc_id.unwrap()).unwrap();
+            model.docs.push((terms, live));
+        }
+        writer.commit().unwrap();
+        check(&dir, &mut rng, &model, &vocabulary, &format!("seed {}, {} documents", seed, model.docs.len()));
+    }
+    writer.force_merge().unwrap();
+    check(&dir, &mut rng, &model, &vocabulary, &format!("seed {}, force merged", seed));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_search_matches_brute_force() {
+    for seed in 0..20 {
+        run(seed);
+    }
+}